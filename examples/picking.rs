@@ -10,7 +10,7 @@ struct PxFontCollection {
 
 fn main() {
     App::new()
-        .add_plugins((DefaultPlugins, PxtxtPlugin::default()))
+        .add_plugins((DefaultPlugins, PxtxtPlugin))
         .init_collection::<PxFontCollection>()
         .insert_resource(Msaa::Off)
         .add_systems(Startup, setup)
@@ -0,0 +1,47 @@
+use bevy::prelude::*;
+use bevy_asset_loader::asset_collection::{AssetCollection, AssetCollectionApp};
+use bevy_pxtxt::{plugin::PxtxtPlugin, pxfont::PxFont, pxtext::{PxText, PxTextMeshBundle, PxTextSection}};
+
+#[derive(AssetCollection, Resource)]
+struct PxFontCollection {
+    #[asset(path = "moonshock.ron")]
+    pub moonshock: Handle<PxFont>,
+}
+
+fn main() {
+    App::new()
+        .add_plugins((DefaultPlugins, PxtxtPlugin))
+        .init_collection::<PxFontCollection>()
+        .insert_resource(Msaa::Off)
+        .add_systems(Startup, setup)
+        .add_systems(Update, tick)
+        .run();
+}
+
+fn setup(
+    fonts: Res<PxFontCollection>,
+    mut commands: Commands,
+) {
+    commands.spawn(Camera2dBundle::default());
+    commands.spawn(PxTextMeshBundle {
+        text: PxText::from_sections(
+            vec![
+                PxTextSection::new("Time: ")
+                    .with_color(Color::srgb(0.5, 0.5, 0.5)),
+                PxTextSection::new("0.00"),
+            ], fonts.moonshock.clone()
+        ),
+        transform: Transform::from_scale(Vec3::splat(4.0)),
+        ..Default::default()
+    });
+}
+
+/// Changes the text every frame, which the mesh path redraws without a new image
+fn tick(
+    time: Res<Time>,
+    mut q_text: Query<&mut PxText>,
+) {
+    for mut text in &mut q_text {
+        text.sections[1].value = format!("{:.2}", time.elapsed_seconds());
+    }
+}
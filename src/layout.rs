@@ -0,0 +1,1375 @@
+use std::{borrow::Cow, ops::Range};
+
+use bevy::prelude::*;
+use unicode_bidi::{BidiInfo, Level};
+
+use crate::{pxfont::PxFont, pxtext::{FitMode, Hyphenation, OverflowMode, PxText, PxTextSection, TabAlign, TabStops, TextDirection, WrapMode, WritingMode}};
+
+const SOFT_HYPHEN: char = '\u{AD}';
+
+/// Characters that are never drawn, though they still affect line breaking
+const INVISIBLE: [char; 5] = [SOFT_HYPHEN, '\u{200B}', '\u{200C}', '\u{200D}', '\u{2060}'];
+
+/// A glyph that has been given a place in the text image
+#[derive(Debug, Clone)]
+pub(crate) struct LaidOutGlyph {
+    pub(crate) c: char,
+    /// Index of the character across all sections, as `PickableText::Chars` counts them
+    pub(crate) index: usize,
+    pub(crate) section: usize,
+    pub(crate) line: usize,
+    /// Top left corner of the glyph within the image.
+    /// While lines are being built, `pos.x` is the offset along the line instead.
+    pub(crate) pos: UVec2,
+    /// Where the glyph is in the font's source image, or `None` for blank space
+    pub(crate) src_rect: Option<URect>,
+    pub(crate) size: UVec2,
+    /// Length of the glyph along its line
+    pub(crate) advance: u32,
+    /// Space after the glyph along its line
+    pub(crate) spacing: i32,
+    /// How many image pixels each pixel of the glyph takes up on each side
+    pub(crate) scale: u32,
+    /// Whether the line may break right before this glyph
+    pub(crate) can_break: bool,
+    /// Whether the word may be hyphenated right before this glyph
+    pub(crate) soft_break: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct LaidOutLine {
+    /// Area of the image the line runs through
+    pub(crate) rect: URect,
+}
+
+/// Where every glyph of a `PxText` ends up, shared by drawing and picking
+#[derive(Debug, Clone)]
+pub(crate) struct TextLayout {
+    pub(crate) glyphs: Vec<LaidOutGlyph>,
+    pub(crate) lines: Vec<LaidOutLine>,
+    /// Size of the canvas the glyphs are drawn on
+    pub(crate) size: UVec2,
+    /// Size the lines take up, ignoring the bounding box
+    pub(crate) content: UVec2,
+    /// Size of the final image, which differs from `size` when shrunk to fit
+    pub(crate) image_size: UVec2,
+    /// How much the canvas is scaled down to fit in the image
+    pub(crate) scale: f32,
+    /// Whether any text didn't fit within the bounding box
+    pub(crate) overflowed: bool,
+    /// Whether lines run from top to bottom
+    pub(crate) vertical: bool,
+    /// How many pages of the bounding box the lines fill
+    pub(crate) pages: usize,
+    /// Index of the page laid out, and the indices of the characters on it
+    pub(crate) page: (usize, Range<usize>),
+}
+
+impl TextLayout {
+    /// Finds the rects around each run of glyphs on a line that match `includes`.
+    /// Each rect covers the whole line across, even if the glyphs are shorter.
+    pub(crate) fn runs(&self, includes: impl Fn(&LaidOutGlyph) -> bool) -> Vec<URect> {
+        let mut rects = Vec::new();
+        for (line_index, line) in self.lines.iter().enumerate() {
+            let line_glyphs = self.glyphs.iter().filter(|g| g.line == line_index);
+            let mut run: Option<URect> = None;
+
+            // Reordered text can split a range into several runs on one line
+            for glyph in line_glyphs.map(Some).chain([None]) {
+                match glyph {
+                    Some(glyph) if includes(glyph) => {
+                        let rect = URect::from_corners(glyph.pos, glyph.pos + glyph.size);
+                        run = Some(run.map_or(rect, |run| run.union(rect)));
+                    }
+                    _ => if let Some(mut run) = run.take() {
+                        if self.vertical {
+                            run.min.x = line.rect.min.x;
+                            run.max.x = line.rect.max.x;
+                        } else {
+                            run.min.y = line.rect.min.y;
+                            run.max.y = line.rect.max.y;
+                        }
+                        rects.push(run);
+                    }
+                }
+            }
+        }
+
+        rects
+    }
+}
+
+/// The last layout of a `PxText`, kept so the text can be drawn again without laying it out
+#[derive(Debug, Component, Clone)]
+pub(crate) struct StoredLayout {
+    /// The text with the scale and font it was fitted with
+    pub(crate) text: PxText,
+    pub(crate) layout: TextLayout,
+}
+
+impl StoredLayout {
+    pub(crate) fn new(fitted: FittedText) -> Self {
+        Self {
+            text: fitted.text.into_owned(),
+            layout: fitted.layout,
+        }
+    }
+
+    /// Whether the text has to be drawn again every frame
+    pub(crate) fn animated(&self) -> bool {
+        self.text.sections.iter().any(|section| section.effect.is_some())
+    }
+}
+
+struct BuiltLine {
+    glyphs: Vec<LaidOutGlyph>,
+    /// Index and section of the first character cut off the end of the line
+    cut: Option<(usize, usize)>,
+    indent: u32,
+    /// Whether the line ends its paragraph
+    paragraph_end: bool,
+}
+
+/// How far glyphs advance along a line
+#[derive(Debug, Clone, Copy)]
+struct Advance {
+    /// The font's vertical advance, when lines run from top to bottom
+    vertical: Option<u32>,
+    /// Default space between glyphs along the line
+    spacing: u32,
+}
+
+impl Advance {
+    fn new(font: &PxFont, writing_mode: WritingMode) -> Self {
+        match writing_mode {
+            WritingMode::HorizontalTb => Self {
+                vertical: None,
+                spacing: font.spacing,
+            },
+            WritingMode::VerticalRl | WritingMode::VerticalLr => Self {
+                vertical: Some(font.vertical_advance),
+                spacing: 0,
+            },
+        }
+    }
+
+    fn of(&self, src_rect: URect, scale: u32) -> u32 {
+        self.vertical.unwrap_or(src_rect.width() + 1) * scale
+    }
+
+    fn spacing_of(&self, section: &PxTextSection) -> i32 {
+        section.letter_spacing.unwrap_or(self.spacing as i32) * section.scale as i32
+    }
+}
+
+struct LineBuilder<'a> {
+    font: &'a PxFont,
+    advance: Advance,
+    max_width: Option<u32>,
+    wrap_mode: WrapMode,
+    first_line_indent: u32,
+    hanging_indent: u32,
+    lines: Vec<BuiltLine>,
+    current: Vec<LaidOutGlyph>,
+    cut: Option<(usize, usize)>,
+    /// Whether the current line is the first of its paragraph
+    starts_paragraph: bool,
+}
+
+impl<'a> LineBuilder<'a> {
+    fn indent(&self) -> u32 {
+        match self.starts_paragraph {
+            true => self.first_line_indent,
+            false => self.hanging_indent,
+        }
+    }
+
+    /// Room left on the current line after its indent
+    fn available_width(&self) -> Option<u32> {
+        self.max_width.map(|max_width| max_width.saturating_sub(self.indent()))
+    }
+
+    fn finish_line(&mut self, paragraph_end: bool) {
+        let glyphs = std::mem::take(&mut self.current);
+        self.lines.push(BuiltLine {
+            glyphs,
+            cut: self.cut.take(),
+            indent: self.indent(),
+            paragraph_end,
+        });
+        self.starts_paragraph = paragraph_end;
+    }
+
+    fn break_line(&mut self) {
+        self.finish_line(false);
+    }
+
+    fn end_paragraph(&mut self) {
+        self.finish_line(true);
+    }
+
+    /// Moves the partial word at the end of the current line onto a new line,
+    /// hyphenating it if that leaves more on the line.
+    /// Returns `false` if the line has no break opportunity to wrap at.
+    fn break_before_word(&mut self, next: &LaidOutGlyph) -> bool {
+        let max_width = self.available_width().unwrap_or(u32::MAX);
+        let hyphen = self.font.char_map.get(&'-');
+        let hyphen_scale = self.current.last().map(|g| g.scale).unwrap_or(1);
+        let hyphen_width = hyphen
+            .map(|glyph| self.advance.of(glyph.src_rect, hyphen_scale))
+            .unwrap_or_default();
+
+        let Some((split, hyphenated)) = (1..=self.current.len()).rev().find_map(|i| {
+            let after = self.current.get(i).unwrap_or(next);
+            if after.can_break {
+                Some((i, false))
+            } else if after.soft_break
+                && line_end(&self.current[..i]) + hyphen_width <= max_width {
+                Some((i, true))
+            } else {
+                None
+            }
+        }) else {
+            return false;
+        };
+
+        let word = self.current.split_off(split);
+        if let (true, Some(glyph), Some(last)) = (hyphenated, hyphen, self.current.last()) {
+            self.current.push(LaidOutGlyph {
+                c: '-',
+                pos: UVec2::new(line_end(&self.current), 0),
+                src_rect: Some(glyph.src_rect),
+                size: (glyph.src_rect.size() + UVec2::ONE) * last.scale,
+                advance: self.advance.of(glyph.src_rect, last.scale),
+                can_break: false,
+                soft_break: false,
+                ..last.clone()
+            });
+        }
+        self.break_line();
+
+        // Lay the word out again from the start of the line, as spacing can pull glyphs back
+        for mut g in word {
+            g.pos.x = line_end(&self.current);
+            self.current.push(g);
+        }
+        true
+    }
+
+    fn push(&mut self, mut glyph: LaidOutGlyph) {
+        let mut x = line_end(&self.current);
+
+        if let Some(max_width) = self.available_width() {
+            if x + glyph.advance > max_width && !self.current.is_empty() {
+                match self.wrap_mode {
+                    WrapMode::Truncate => {
+                        self.cut.get_or_insert((glyph.index, glyph.section));
+                        return;
+                    }
+                    _ if breaking_space(glyph.c) => {
+                        // The break replaces the whitespace
+                        self.break_line();
+                        return;
+                    }
+                    WrapMode::WrapWord | WrapMode::WrapUnicode => {
+                        if !self.break_before_word(&glyph) {
+                            self.break_line();
+                        }
+                    }
+                    WrapMode::WrapChar => self.break_line(),
+                    WrapMode::NoWrap => unreachable!(),
+                }
+                x = line_end(&self.current);
+            }
+        }
+
+        glyph.pos.x = x;
+        self.current.push(glyph);
+    }
+}
+
+/// Where the next glyph on a line would go
+fn line_end(line: &[LaidOutGlyph]) -> u32 {
+    line.last()
+        .map(|g| (g.pos.x + g.advance).saturating_add_signed(g.spacing))
+        .unwrap_or_default()
+}
+
+/// Replaces the end of a line with an ellipsis, so that it fits in `max_width`.
+/// `stand_in` is the index and section of the first character that was cut.
+fn ellipsize(
+    line: &mut Vec<LaidOutGlyph>,
+    mut stand_in: (usize, usize),
+    font: &PxFont,
+    advance: Advance,
+    section: &PxTextSection,
+    max_width: Option<u32>,
+) {
+    let spacing = advance.spacing_of(section);
+    let dots = if let Some(glyph) = font.char_map.get(&'…') {
+        vec![('…', glyph)]
+    } else if let Some(glyph) = font.char_map.get(&'.') {
+        vec![('.', glyph); 3]
+    } else {
+        return;
+    };
+
+    let dots_width = dots
+        .iter()
+        .map(|(_, glyph)| advance.of(glyph.src_rect, section.scale))
+        .sum::<u32>()
+        .saturating_add_signed(spacing * (dots.len() as i32 - 1));
+
+    while let Some(last) = line.last() {
+        let fits = max_width.map_or(true, |max_width| {
+            line_end(line) + dots_width <= max_width
+        });
+
+        if fits && !last.c.is_whitespace() {
+            break;
+        }
+
+        stand_in = (last.index, last.section);
+        line.pop();
+    }
+
+    for (c, glyph) in dots {
+        let x = line_end(line);
+        line.push(LaidOutGlyph {
+            c,
+            index: stand_in.0,
+            section: stand_in.1,
+            line: 0,
+            pos: UVec2::new(x, 0),
+            src_rect: Some(glyph.src_rect),
+            size: (glyph.src_rect.size() + UVec2::ONE) * section.scale,
+            advance: advance.of(glyph.src_rect, section.scale),
+            spacing,
+            scale: section.scale,
+            can_break: false,
+            soft_break: false,
+        });
+    }
+}
+
+/// Lays out the text, showing only the lines on `page` when it's given.
+/// Pages split on line boundaries, each holding as many lines as fit in the bounding box.
+pub(crate) fn layout_text(text: &PxText, font: &PxFont, page: Option<usize>) -> TextLayout {
+    // The scale is a public field, so it can be 0 without going through `with_scale`
+    let text = match text.sections.iter().any(|section| section.scale == 0) {
+        true => Cow::Owned(PxText {
+            sections: text.sections
+                .iter()
+                .map(|section| PxTextSection { scale: section.scale.max(1), ..section.clone() })
+                .collect(),
+            ..text.clone()
+        }),
+        false => Cow::Borrowed(text),
+    };
+    let text = text.as_ref();
+    let vertical = text.writing_mode != WritingMode::HorizontalTb;
+    let advance = Advance::new(font, text.writing_mode);
+    // Lines are stacked along the block axis, and glyphs advance along the inline axis
+    let to_xy = |inline: u32, block: u32| match vertical {
+        true => UVec2::new(block, inline),
+        false => UVec2::new(inline, block),
+    };
+    let column_width = font.char_map
+        .values()
+        .map(|glyph| glyph.src_rect.width() + 1)
+        .max()
+        .unwrap_or_default();
+    // Distance from the top of a line to its baseline, and the line's height
+    let line_metrics = |line: &[LaidOutGlyph]| {
+        let scale = line.iter().map(|g| g.scale).max().unwrap_or(1);
+        match vertical {
+            true => (0, column_width * scale),
+            false => (font.ascender * scale, (font.ascender + font.descender) * scale),
+        }
+    };
+    let bounds = text.bounding_box.map(|bounds| to_xy(bounds.x, bounds.y));
+
+    let shrink = text.overflow == OverflowMode::ShrinkToFit;
+    let max_width = match text.wrap_mode {
+        WrapMode::NoWrap => None,
+        WrapMode::Truncate if shrink => None,
+        _ => bounds.map(|bounds| bounds.x),
+    };
+    let max_height = match shrink {
+        true => None,
+        false => bounds.map(|bounds| bounds.y),
+    };
+
+    let mut builder = LineBuilder {
+        font,
+        advance,
+        max_width,
+        wrap_mode: text.wrap_mode,
+        first_line_indent: text.first_line_indent,
+        hanging_indent: text.hanging_indent,
+        lines: Vec::new(),
+        current: Vec::new(),
+        cut: None,
+        starts_paragraph: true,
+    };
+
+    let chars: Vec<(char, usize)> = text.sections
+        .iter()
+        .enumerate()
+        .flat_map(|(section, s)| s.value.chars().map(move |c| (c, section)))
+        .collect();
+    let line_breaks = line_breaks(&chars, text.wrap_mode);
+    let soft_breaks = soft_breaks(&chars, text.hyphenation);
+    let bidi_levels = match vertical {
+        true => None,
+        false => bidi_levels(&chars, text.direction),
+    };
+
+    for (index, &(c, section)) in chars.iter().enumerate() {
+        let scale = text.sections[section].scale;
+        let blank = |length: u32| LaidOutGlyph {
+            c,
+            index,
+            section,
+            line: 0,
+            pos: UVec2::ZERO,
+            src_rect: None,
+            size: to_xy(length, (font.ascender + font.descender) * scale),
+            advance: length,
+            spacing: advance.spacing_of(&text.sections[section]),
+            scale,
+            can_break: line_breaks[index],
+            soft_break: soft_breaks[index],
+        };
+
+        if c == '\n' {
+            builder.end_paragraph();
+        } else if INVISIBLE.contains(&c) {
+            // Soft hyphens are only drawn as a hyphen if the line breaks there
+        } else if c == '\t' {
+            let x = line_end(&builder.current);
+            let length = tab_length(text, font, advance, x, section, &chars[index + 1..]);
+            builder.push(blank(length));
+        } else if let (Some(width), ' ', None) = (font.space_width, c, advance.vertical) {
+            builder.push(blank(width * scale));
+        } else if let Some(glyph) = font.char_map.get(&c) {
+            builder.push(LaidOutGlyph {
+                src_rect: Some(glyph.src_rect),
+                size: (glyph.src_rect.size() + UVec2::ONE) * scale,
+                advance: advance.of(glyph.src_rect, scale),
+                ..blank(0)
+            });
+        } else {
+            error!("The font {} does not contain the character {c}", font.name);
+        }
+    }
+    builder.end_paragraph();
+
+    let mut built = builder.lines;
+    let mut overflowed = built.iter().any(|line| line.cut.is_some());
+
+    // Where each line starts along the block axis, along with its baseline and height
+    let mut offsets = Vec::with_capacity(built.len());
+    let mut offset = 0;
+    for line in &built {
+        let (baseline, line_height) = line_metrics(&line.glyphs);
+        offsets.push((offset, baseline, line_height));
+
+        let line_spacing = line.glyphs
+            .last()
+            .and_then(|g| text.sections[g.section].line_spacing)
+            .unwrap_or(text.line_spacing);
+        let paragraph_spacing = match line.paragraph_end {
+            true => text.paragraph_spacing as i32,
+            false => 0,
+        };
+        offset += (line_height as i32 + line_spacing + paragraph_spacing).max(0) as u32;
+    }
+
+    // Start a new page at each line that doesn't fit with the lines before it on the page
+    let max_lines = text.max_lines.unwrap_or(u32::MAX) as usize;
+    let mut page_starts = vec![0];
+    for (i, (offset, _, line_height)) in offsets.iter().enumerate().skip(1) {
+        let start = *page_starts.last().unwrap();
+        let top = offsets[start].0;
+        if i - start >= max_lines
+            || max_height.is_some_and(|max_height| offset + line_height - top > max_height) {
+            page_starts.push(i);
+        }
+    }
+
+    // Drop the lines that don't fit, marking where the text was cut off
+    let visible_lines = offsets
+        .iter()
+        .take_while(|(offset, _, line_height)| {
+            max_height.map_or(true, |max_height| offset + line_height <= max_height)
+        })
+        .count()
+        .min(max_lines);
+
+    // Lines after the page aren't cut off, just shown later
+    let mut shown_page = (0, 0..chars.len());
+    if let Some(page) = page {
+        let page = page.min(page_starts.len() - 1);
+        let start = page_starts[page];
+        let end = page_starts.get(page + 1).copied().unwrap_or(built.len());
+        let top = offsets.get(start).map_or(0, |(offset, ..)| *offset);
+        let first_index = |lines: &[BuiltLine]| lines.iter().find_map(|line| line.glyphs.first()).map(|g| g.index);
+        shown_page = (
+            page,
+            first_index(&built[start..]).unwrap_or(chars.len())..first_index(&built[end..]).unwrap_or(chars.len()),
+        );
+
+        built.truncate(end);
+        built.drain(..start);
+        offsets.truncate(end);
+        offsets.drain(..start);
+        for (offset, ..) in &mut offsets {
+            *offset -= top;
+        }
+    } else if built.len() > visible_lines {
+        overflowed = true;
+        let cut = built[visible_lines..]
+            .iter()
+            .find_map(|line| line.glyphs.first())
+            .map(|g| (g.index, g.section));
+        built.truncate(visible_lines);
+
+        if let (Some(last), Some(cut)) = (built.last_mut(), cut) {
+            last.cut.get_or_insert(cut);
+        }
+    }
+
+    let mut placed = Vec::new();
+
+    for (BuiltLine { glyphs: mut line, cut, indent, .. }, offset) in built.into_iter().zip(offsets) {
+        if let (OverflowMode::Ellipsis, Some(cut)) = (text.overflow, cut) {
+            let max_width = max_width.map(|max_width| max_width.saturating_sub(indent));
+            ellipsize(&mut line, cut, font, advance, &text.sections[cut.1], max_width);
+        }
+
+        let rtl = match &bidi_levels {
+            Some(levels) => reorder_line(&mut line, levels, font, advance),
+            None => false,
+        };
+
+        let length = line
+            .last()
+            .map(|g| g.pos.x + g.advance)
+            .unwrap_or_default() + indent;
+
+        placed.push((line, offset, length, indent, rtl));
+    }
+
+    let content = to_xy(
+        placed.iter().map(|(_, _, length, _, _)| *length).max().unwrap_or_default(),
+        placed
+            .iter()
+            .map(|(_, (offset, _, line_height), _, _, _)| offset + line_height)
+            .max()
+            .unwrap_or_default(),
+    );
+
+    let size = match text.bounding_box {
+        Some(bounds) if shrink => bounds.max(content),
+        Some(bounds) if text.wrap_mode == WrapMode::NoWrap => {
+            bounds.max(to_xy(if vertical { content.y } else { content.x }, 0))
+        }
+        Some(bounds) => bounds,
+        None => content,
+    }.max(UVec2::ONE);
+
+    let mut glyphs = Vec::new();
+    let mut lines = Vec::new();
+
+    for (line, (offset, baseline, line_height), length, indent, rtl) in placed {
+        let rect = match text.writing_mode {
+            WritingMode::HorizontalTb => URect::new(0, offset, size.x, offset + line_height),
+            WritingMode::VerticalLr => URect::new(offset, 0, offset + line_height, size.y),
+            WritingMode::VerticalRl => {
+                let x = size.x.saturating_sub(offset + line_height);
+                URect::new(x, 0, x + line_height, size.y)
+            }
+        };
+
+        for mut glyph in line {
+            glyph.line = lines.len();
+            glyph.pos = match vertical {
+                // Centered in the column
+                true => UVec2::new(
+                    rect.min.x + line_height.saturating_sub(glyph.size.x) / 2,
+                    glyph.pos.x + indent,
+                ),
+                // Right to left paragraphs start from the right edge
+                false if rtl => UVec2::new(
+                    glyph.pos.x + size.x.saturating_sub(length),
+                    rect.min.y + baseline - font.ascender * glyph.scale,
+                ),
+                false => UVec2::new(
+                    glyph.pos.x + indent,
+                    rect.min.y + baseline - font.ascender * glyph.scale,
+                ),
+            };
+            glyphs.push(glyph);
+        }
+
+        lines.push(LaidOutLine { rect });
+    }
+
+    let (image_size, scale) = match text.bounding_box {
+        Some(bounds) if shrink && size != bounds => {
+            overflowed = true;
+            let scale = bounds.as_vec2() / size.as_vec2();
+            (bounds.max(UVec2::ONE), scale.min_element())
+        }
+        _ => (size, 1.),
+    };
+
+    TextLayout {
+        glyphs,
+        lines,
+        size,
+        content,
+        image_size,
+        scale,
+        overflowed,
+        vertical,
+        pages: page_starts.len(),
+        page: shown_page,
+    }
+}
+
+/// A layout of a `PxText` at the scale and font its `FitMode` picked
+pub(crate) struct FittedText<'a> {
+    /// The text with its sections scaled and its font replaced
+    pub(crate) text: Cow<'a, PxText>,
+    pub(crate) scale: u32,
+    pub(crate) layout: TextLayout,
+}
+
+/// Whether a font can be laid out with yet
+pub(crate) enum FontLoad<'a> {
+    Loaded(&'a PxFont),
+    Loading,
+    Failed,
+}
+
+impl<'a> FontLoad<'a> {
+    fn loaded(self) -> Option<&'a PxFont> {
+        match self {
+            FontLoad::Loaded(font) => Some(font),
+            _ => None,
+        }
+    }
+}
+
+/// Lays out the text at the largest scale or the first font it fits in, showing `page` if given.
+/// Returns `None` until the fonts it could pick are loaded, or if they all failed.
+/// Only fonts that failed are passed over, so the same font is picked however the fonts load.
+pub(crate) fn fit_text<'a>(
+    text: &'a PxText,
+    page: Option<usize>,
+    get_font: impl Fn(&Handle<PxFont>) -> FontLoad<'a>,
+) -> Option<FittedText<'a>> {
+    let fits = |layout: &TextLayout| !layout.overflowed && text.bounding_box
+        .map_or(true, |bounds| layout.size.cmple(bounds).all());
+
+    match &text.fit {
+        FitMode::None => {
+            let font = get_font(&text.font).loaded()?;
+            let layout = layout_text(text, font, page);
+            Some(FittedText { text: Cow::Borrowed(text), scale: 1, layout })
+        }
+        FitMode::Scale(max_scale) => {
+            let font = get_font(&text.font).loaded()?;
+            let mut fitted = None;
+            for scale in (1..=(*max_scale).max(1)).rev() {
+                let mut scaled = text.clone();
+                for section in &mut scaled.sections {
+                    section.scale *= scale;
+                }
+                let layout = layout_text(&scaled, font, page);
+                let done = fits(&layout);
+                fitted = Some(FittedText { text: Cow::Owned(scaled), scale, layout });
+                if done {
+                    break;
+                }
+            }
+            fitted
+        }
+        FitMode::Fonts(fallbacks) => {
+            let mut fitted = None;
+            for handle in std::iter::once(&text.font).chain(fallbacks) {
+                let font = match get_font(handle) {
+                    FontLoad::Loaded(font) => font,
+                    FontLoad::Loading => return None,
+                    FontLoad::Failed => continue,
+                };
+                let text = match *handle == text.font {
+                    true => Cow::Borrowed(text),
+                    false => Cow::Owned(PxText { font: handle.clone(), ..text.clone() }),
+                };
+                let layout = layout_text(&text, font, page);
+                let done = fits(&layout);
+                fitted = Some(FittedText { text, scale: 1, layout });
+                if done {
+                    break;
+                }
+            }
+            fitted
+        }
+    }
+}
+
+/// Finds how far a tab has to advance from `x` to reach the next tab stop.
+/// `following` is the text after the tab, which right and decimal stops align.
+fn tab_length(
+    text: &PxText,
+    font: &PxFont,
+    advance: Advance,
+    x: u32,
+    section: usize,
+    following: &[(char, usize)],
+) -> u32 {
+    let scale = text.sections[section].scale;
+    let space = font.space_width
+        .map(|width| width * scale)
+        .or_else(|| font.char_map.get(&' ').map(|glyph| advance.of(glyph.src_rect, scale)))
+        .unwrap_or_default();
+
+    let (position, align) = match &text.tab_stops {
+        TabStops::Interval(interval) if *interval > 0 => {
+            ((x / interval + 1) * interval, TabAlign::Left)
+        }
+        TabStops::Interval(_) => return space,
+        TabStops::Positions(stops) => match stops.iter().find(|stop| stop.position > x) {
+            Some(stop) => (stop.position, stop.align),
+            // Past the last stop, a tab is just a space
+            None => return space,
+        },
+    };
+
+    let spacing_of = |section: usize| advance.spacing_of(&text.sections[section]);
+    let length = |aligned_width: i32| {
+        (position as i32 - x as i32 - aligned_width - spacing_of(section)).max(0) as u32
+    };
+
+    // Width of the text that has to end at the tab stop
+    let mut width = 0;
+    let mut last_spacing = 0;
+    if align != TabAlign::Left {
+        for &(c, section) in following {
+            if align == TabAlign::Decimal && c == '.' {
+                // The decimal point itself starts at the tab stop
+                return length(width);
+            }
+
+            if c == '\n' || c == '\t' {
+                break;
+            }
+
+            let scale = text.sections[section].scale;
+            let glyph_advance = match (font.space_width, c, advance.vertical) {
+                (Some(space_width), ' ', None) => space_width * scale,
+                _ => match font.char_map.get(&c) {
+                    Some(glyph) => advance.of(glyph.src_rect, scale),
+                    None => continue,
+                },
+            };
+            last_spacing = spacing_of(section);
+            width += glyph_advance as i32 + last_spacing;
+        }
+    }
+
+    length(width - last_spacing)
+}
+
+fn word_separator(c: char) -> bool {
+    c.is_whitespace() || c == '-'
+}
+
+/// Whitespace that a line can break at instead of drawing
+fn breaking_space(c: char) -> bool {
+    c.is_whitespace() && !matches!(c, '\u{A0}' | '\u{2007}' | '\u{202F}')
+}
+
+/// Finds which characters a line may break before
+fn line_breaks(chars: &[(char, usize)], wrap_mode: WrapMode) -> Vec<bool> {
+    if wrap_mode != WrapMode::WrapUnicode {
+        return (0..chars.len())
+            .map(|i| i > 0 && word_separator(chars[i - 1].0))
+            .collect();
+    }
+
+    let string: String = chars.iter().map(|(c, _)| *c).collect();
+    let offsets: Vec<usize> = string.char_indices().map(|(i, _)| i).collect();
+    let mut breaks = vec![false; chars.len()];
+
+    for (offset, _) in unicode_linebreak::linebreaks(&string) {
+        if let Ok(i) = offsets.binary_search(&offset) {
+            breaks[i] = true;
+        }
+    }
+
+    breaks
+}
+
+/// Finds the bidi embedding level of each character, along with the level of its paragraph
+fn bidi_levels(chars: &[(char, usize)], direction: TextDirection) -> Option<Vec<(Level, Level)>> {
+    let default_level = match direction {
+        TextDirection::LeftToRight => return None,
+        TextDirection::Auto => None,
+        TextDirection::RightToLeft => Some(Level::rtl()),
+    };
+
+    let string: String = chars.iter().map(|(c, _)| *c).collect();
+    let info = BidiInfo::new(&string, default_level);
+
+    Some(string
+        .char_indices()
+        .map(|(i, _)| {
+            let paragraph = info.paragraphs
+                .iter()
+                .find(|p| p.range.contains(&i))
+                .map(|p| p.level)
+                .unwrap_or_else(Level::ltr);
+            (info.levels[i], paragraph)
+        })
+        .collect())
+}
+
+/// Puts a line's glyphs in visual order, mirroring brackets in right to left runs.
+/// Returns whether the line is part of a right to left paragraph.
+fn reorder_line(
+    line: &mut [LaidOutGlyph],
+    levels: &[(Level, Level)],
+    font: &PxFont,
+    advance: Advance,
+) -> bool {
+    let Some(paragraph) = line.first().map(|g| levels[g.index].1) else {
+        return false;
+    };
+
+    let mut line_levels: Vec<u8> = line
+        .iter()
+        .map(|g| levels[g.index].0.number())
+        .collect();
+
+    // Trailing whitespace goes back to the paragraph level
+    for (glyph, level) in line.iter().zip(&mut line_levels).rev() {
+        if !glyph.c.is_whitespace() {
+            break;
+        }
+        *level = paragraph.number();
+    }
+
+    // Reverse every run at or above each odd level, from the highest down
+    let highest = line_levels.iter().copied().max().unwrap_or_default();
+    let lowest_odd = line_levels.iter().copied().min().unwrap_or_default() | 1;
+    for level in (lowest_odd..=highest).rev() {
+        let mut i = 0;
+        while i < line.len() {
+            if line_levels[i] >= level {
+                let start = i;
+                while i < line.len() && line_levels[i] >= level {
+                    i += 1;
+                }
+                line[start..i].reverse();
+                line_levels[start..i].reverse();
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    let mut x = 0;
+    for (glyph, level) in line.iter_mut().zip(line_levels) {
+        if level % 2 == 1 {
+            let mirrored = match glyph.c {
+                '(' => Some(')'),
+                ')' => Some('('),
+                '[' => Some(']'),
+                ']' => Some('['),
+                '{' => Some('}'),
+                '}' => Some('{'),
+                '<' => Some('>'),
+                '>' => Some('<'),
+                _ => None,
+            };
+
+            // The mirrored glyph may be a different width
+            if let Some((c, mirror)) = mirrored.and_then(|c| Some((c, font.char_map.get(&c)?))) {
+                glyph.c = c;
+                glyph.src_rect = Some(mirror.src_rect);
+                glyph.size = (mirror.src_rect.size() + UVec2::ONE) * glyph.scale;
+                glyph.advance = advance.of(mirror.src_rect, glyph.scale);
+            }
+        }
+
+        glyph.pos.x = x;
+        x = (x + glyph.advance).saturating_add_signed(glyph.spacing);
+    }
+
+    paragraph.is_rtl()
+}
+
+/// Finds which characters a word may be hyphenated before
+fn soft_breaks(chars: &[(char, usize)], hyphenation: Hyphenation) -> Vec<bool> {
+    let mut breaks = vec![false; chars.len()];
+
+    for (i, (c, _)) in chars.iter().enumerate() {
+        if *c == SOFT_HYPHEN {
+            if let Some(next) = chars[i..].iter().position(|(c, _)| *c != SOFT_HYPHEN) {
+                breaks[i + next] = true;
+            }
+        }
+    }
+
+    match hyphenation {
+        Hyphenation::Manual => {}
+        #[cfg(feature = "hyphenation")]
+        Hyphenation::Auto(lang) => {
+            let mut start = 0;
+            while start < chars.len() {
+                let len = chars[start..]
+                    .iter()
+                    .position(|(c, _)| !c.is_alphabetic())
+                    .unwrap_or(chars.len() - start);
+
+                if len > 0 {
+                    let word: String = chars[start..start + len].iter().map(|(c, _)| *c).collect();
+                    let mut offset = start;
+                    for syllable in hypher::hyphenate(&word, lang) {
+                        if offset != start {
+                            breaks[offset] = true;
+                        }
+                        offset += syllable.chars().count();
+                    }
+                }
+
+                start += len + 1;
+            }
+        }
+    }
+
+    breaks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pxtext::TabStop, test_support::test_font};
+
+    fn lines(layout: &TextLayout) -> Vec<String> {
+        let mut lines = vec![String::new(); layout.lines.len()];
+        for glyph in &layout.glyphs {
+            lines[glyph.line].push(glyph.c);
+        }
+        lines
+    }
+
+    fn text(value: &str, wrap_mode: WrapMode, bounds: UVec2) -> PxText {
+        let mut text = PxText::from_section(value, Handle::default())
+            .with_bounding_box(bounds);
+        text.wrap_mode = wrap_mode;
+        text
+    }
+
+    #[test]
+    fn word_wrap_keeps_words_whole() {
+        let font = test_font();
+        // "ab cd" is 3 + 1 + 3 + 1 + 2 + 1 + 3 + 1 + 3 = 18 pixels wide
+        let text = text("ab cd ef", WrapMode::WrapWord, UVec2::new(20, 100));
+        let layout = layout_text(&text, &font, None);
+
+        assert_eq!(lines(&layout), ["ab cd", "ef"]);
+        assert_eq!(layout.size, UVec2::new(20, 100));
+    }
+
+    #[test]
+    fn char_wrap_breaks_anywhere() {
+        let font = test_font();
+        let text = text("abcdefg", WrapMode::WrapChar, UVec2::new(16, 100));
+        let layout = layout_text(&text, &font, None);
+
+        assert_eq!(lines(&layout), ["abcd", "efg"]);
+        assert_eq!(layout.glyphs[4].pos, UVec2::new(0, 5));
+    }
+
+    #[test]
+    fn truncate_clips_each_line() {
+        let font = test_font();
+        let text = text("abcdefg\nhi", WrapMode::Truncate, UVec2::new(16, 100));
+        let layout = layout_text(&text, &font, None);
+
+        assert_eq!(lines(&layout), ["abcd", "hi"]);
+        assert!(layout.overflowed);
+    }
+
+    #[test]
+    fn no_wrap_grows_horizontally() {
+        let font = test_font();
+        let text = text("abcdefg\nhi", WrapMode::NoWrap, UVec2::new(16, 100));
+        let layout = layout_text(&text, &font, None);
+
+        assert_eq!(lines(&layout), ["abcdefg", "hi"]);
+        assert_eq!(layout.size, UVec2::new(27, 100));
+        assert!(!layout.overflowed);
+    }
+
+    #[test]
+    fn lines_past_the_bounds_are_dropped() {
+        let font = test_font();
+        let text = text("ab\ncd\nef", WrapMode::WrapChar, UVec2::new(16, 10));
+        let layout = layout_text(&text, &font, None);
+
+        assert_eq!(lines(&layout), ["ab", "cd"]);
+        assert!(layout.overflowed);
+    }
+
+    #[test]
+    fn ellipsis_replaces_the_cut_tail() {
+        let font = test_font();
+        let mut text = text("abcdefg\nhi", WrapMode::Truncate, UVec2::new(16, 100));
+        text.overflow = OverflowMode::Ellipsis;
+        let layout = layout_text(&text, &font, None);
+
+        // The dots take 1 + 1 + 1 + 1 + 1 = 5 pixels, leaving room for "ab"
+        assert_eq!(lines(&layout), ["ab...", "hi"]);
+        assert!(layout.overflowed);
+    }
+
+    #[test]
+    fn ellipsis_marks_dropped_lines() {
+        let font = test_font();
+        let mut text = text("ab cd ef gh", WrapMode::WrapWord, UVec2::new(20, 4));
+        text.overflow = OverflowMode::Ellipsis;
+        let layout = layout_text(&text, &font, None);
+
+        assert_eq!(lines(&layout), ["ab c..."]);
+        // The dots stand in for the "d" they replaced
+        assert_eq!(layout.glyphs.last().unwrap().index, 4);
+    }
+
+    #[test]
+    fn shrink_to_fit_scales_the_canvas() {
+        let font = test_font();
+        let mut text = text("ab\ncd\nef\ngh", WrapMode::WrapWord, UVec2::new(20, 10));
+        text.overflow = OverflowMode::ShrinkToFit;
+        let layout = layout_text(&text, &font, None);
+
+        assert_eq!(lines(&layout), ["ab", "cd", "ef", "gh"]);
+        assert_eq!(layout.size, UVec2::new(20, 19));
+        assert_eq!(layout.image_size, UVec2::new(20, 10));
+        assert_eq!(layout.scale, 10. / 19.);
+        assert!(layout.overflowed);
+    }
+
+    #[test]
+    fn max_lines_clamps_wrapped_text() {
+        let font = test_font();
+        let mut text = PxText::from_section("ab cd ef gh ij", Handle::default())
+            .with_max_lines(2)
+            .with_overflow(OverflowMode::Ellipsis);
+        text.bounding_box = None;
+        let layout = layout_text(&text, &font, None);
+        assert_eq!(lines(&layout), ["ab cd ef gh ij"]);
+
+        let text = text.with_bounding_box(UVec2::new(20, 100));
+        let layout = layout_text(&text, &font, None);
+        assert_eq!(lines(&layout), ["ab cd", "ef g..."]);
+        assert!(layout.overflowed);
+    }
+
+    #[test]
+    fn max_lines_sets_the_height() {
+        let font = test_font();
+        let text = PxText::from_section("ab\ncd\nef", Handle::default())
+            .with_max_lines(2);
+        let layout = layout_text(&text, &font, None);
+
+        assert_eq!(lines(&layout), ["ab", "cd"]);
+        assert_eq!(layout.size.y, 9);
+
+        // The image keeps the bounding box, but the lines only take up two of them
+        let text = text.with_bounding_box(UVec2::new(20, 100));
+        let layout = layout_text(&text, &font, None);
+        assert_eq!(layout.size.y, 100);
+        assert_eq!(layout.content.y, 9);
+    }
+
+    #[test]
+    fn soft_hyphens_only_show_when_used() {
+        let font = test_font();
+        let text = text("ab\u{AD}cdef ab\u{AD}c", WrapMode::WrapWord, UVec2::new(16, 100));
+        let layout = layout_text(&text, &font, None);
+
+        assert_eq!(lines(&layout), ["ab-", "cdef", "abc"]);
+    }
+
+    #[cfg(feature = "hyphenation")]
+    #[test]
+    fn dictionary_hyphenation() {
+        let font = test_font();
+        let text = text("tomate", WrapMode::WrapWord, UVec2::new(16, 100))
+            .with_hyphenation(Hyphenation::Auto(hypher::Lang::German));
+        let layout = layout_text(&text, &font, None);
+
+        assert_eq!(lines(&layout), ["to-", "mate"]);
+    }
+
+    #[test]
+    fn unicode_wrap_follows_break_rules() {
+        let font = test_font();
+
+        // No break is allowed before the "。"
+        let cjk = text("日本語。日本", WrapMode::WrapUnicode, UVec2::new(12, 100));
+        let layout = layout_text(&cjk, &font, None);
+        assert_eq!(lines(&layout)[0], "日本");
+
+        let nbsp = text("ab\u{A0}cd", WrapMode::WrapUnicode, UVec2::new(16, 100));
+        let layout = layout_text(&nbsp, &font, None);
+        assert_eq!(lines(&layout), ["ab\u{A0}c", "d"]);
+
+        let zwsp = text("abc\u{200B}def", WrapMode::WrapUnicode, UVec2::new(16, 100));
+        let layout = layout_text(&zwsp, &font, None);
+        assert_eq!(lines(&layout), ["abc", "def"]);
+    }
+
+    #[test]
+    fn bidi_reorders_right_to_left_runs() {
+        let font = test_font();
+
+        let ltr = PxText::from_section("ab אבג", Handle::default())
+            .with_direction(TextDirection::Auto);
+        let layout = layout_text(&ltr, &font, None);
+        assert_eq!(lines(&layout), ["ab גבא"]);
+
+        let rtl = PxText::from_section("אבג ab", Handle::default())
+            .with_direction(TextDirection::Auto)
+            .with_bounding_box(UVec2::new(40, 100));
+        let layout = layout_text(&rtl, &font, None);
+        assert_eq!(lines(&layout), ["ab גבא"]);
+        // Still mapped to the logical index of the "a", and aligned to the right
+        assert_eq!(layout.glyphs[0].index, 4);
+        let last = layout.glyphs.last().unwrap();
+        assert_eq!(last.pos.x + last.size.x, 40);
+
+        // Mirrored brackets take the width of the glyph they're drawn with
+        let mirrored = PxText::from_section("א(ב", Handle::default())
+            .with_direction(TextDirection::Auto);
+        let layout = layout_text(&mirrored, &font, None);
+        assert_eq!(lines(&layout), ["ב)א"]);
+        assert_eq!(layout.glyphs[1].size.x, 3);
+        assert_eq!(layout.glyphs[2].pos.x, 8);
+    }
+
+    #[test]
+    fn vertical_columns() {
+        let font = test_font();
+        let text = text("日本語\nab", WrapMode::WrapChar, UVec2::new(20, 12))
+            .with_writing_mode(WritingMode::VerticalRl);
+        let layout = layout_text(&text, &font, None);
+
+        // Only two glyphs fit in each 12 pixel tall column
+        assert_eq!(lines(&layout), ["日本", "語", "ab"]);
+        let positions: Vec<UVec2> = layout.glyphs.iter().map(|g| g.pos).collect();
+        assert_eq!(positions, [
+            UVec2::new(17, 0),
+            UVec2::new(17, 5),
+            UVec2::new(13, 0),
+            UVec2::new(9, 0),
+            UVec2::new(9, 5),
+        ]);
+    }
+
+    #[test]
+    fn tabs_advance_to_stops() {
+        let font = test_font();
+        let starts = |text: &PxText| -> Vec<u32> {
+            layout_text(text, &font, None).glyphs
+                .iter()
+                .filter(|g| g.c != '\t')
+                .map(|g| g.pos.x)
+                .collect()
+        };
+
+        let interval = PxText::from_section("a\tb", Handle::default())
+            .with_tab_stops(TabStops::Interval(10));
+        assert_eq!(starts(&interval), [0, 10]);
+
+        let positions = PxText::from_section("a\tbc\td.ef", Handle::default())
+            .with_tab_stops(TabStops::Positions(vec![
+                TabStop::new(20, TabAlign::Right),
+                TabStop::new(40, TabAlign::Decimal),
+            ]));
+        // "bc" ends at 20, and "d" ends where the "." starts at 40
+        assert_eq!(starts(&positions), [0, 13, 17, 36, 40, 42, 46]);
+    }
+
+    #[test]
+    fn space_width_replaces_the_space_glyph() {
+        let mut font = test_font();
+        font.space_width = Some(5);
+        let text = PxText::from_section("a b", Handle::default());
+        let layout = layout_text(&text, &font, None);
+
+        assert_eq!(layout.glyphs[1].src_rect, None);
+        assert_eq!(layout.glyphs[2].pos.x, 10);
+    }
+
+    #[test]
+    fn paragraph_spacing_and_indents() {
+        let font = test_font();
+        let text = PxText::from_section("ab cd ef\ngh", Handle::default())
+            .with_bounding_box(UVec2::new(20, 100))
+            .with_paragraph_spacing(3)
+            .with_indents(4, 2);
+        let layout = layout_text(&text, &font, None);
+
+        assert_eq!(lines(&layout), ["ab ", "cd ef", "gh"]);
+        let starts: Vec<UVec2> = layout.lines
+            .iter()
+            .enumerate()
+            .map(|(i, _)| layout.glyphs.iter().find(|g| g.line == i).unwrap().pos)
+            .collect();
+        // Only the end of a paragraph gets the extra spacing
+        assert_eq!(starts, [UVec2::new(4, 0), UVec2::new(2, 5), UVec2::new(4, 13)]);
+    }
+
+    #[test]
+    fn line_spacing_can_be_negative_or_per_section() {
+        let font = test_font();
+        let text = PxText::from_sections(vec![
+            PxTextSection::new("ab\n"),
+            PxTextSection::new("cd\nef").with_line_spacing(4),
+        ], Handle::default()).with_line_spacing(-1);
+        let layout = layout_text(&text, &font, None);
+
+        let tops: Vec<u32> = layout.lines.iter().map(|l| l.rect.min.y).collect();
+        assert_eq!(tops, [0, 3, 11]);
+        assert_eq!(layout.size.y, 15);
+    }
+
+    #[test]
+    fn letter_spacing_per_section() {
+        let font = test_font();
+        let text = PxText::from_sections(vec![
+            PxTextSection::new("ab").with_letter_spacing(3),
+            PxTextSection::new("cd").with_letter_spacing(-1),
+            PxTextSection::new("ef"),
+        ], Handle::default());
+        let layout = layout_text(&text, &font, None);
+
+        let starts: Vec<u32> = layout.glyphs.iter().map(|g| g.pos.x).collect();
+        assert_eq!(starts, [0, 6, 12, 14, 16, 20]);
+        assert_eq!(layout.size.x, 23);
+    }
+
+    #[test]
+    fn wrapped_words_keep_negative_letter_spacing() {
+        let font = test_font();
+        let text = PxText::from_sections(vec![
+            PxTextSection::new("ab "),
+            PxTextSection::new("c").with_letter_spacing(-5),
+            PxTextSection::new("def").with_letter_spacing(20),
+        ], Handle::default()).with_bounding_box(UVec2::new(30, 20));
+        let layout = layout_text(&text, &font, None);
+
+        let starts: Vec<(usize, u32)> = layout.glyphs.iter().map(|g| (g.line, g.pos.x)).collect();
+        // The tracking after "c" is wider than it, pulling "d" back to the start of the line
+        assert_eq!(starts, [(0, 0), (0, 4), (0, 8), (1, 0), (1, 0), (1, 23), (2, 0)]);
+    }
+
+    #[test]
+    fn scaled_sections_share_a_baseline() {
+        let font = test_font();
+        let text = PxText::from_sections(vec![
+            PxTextSection::new("ab").with_scale(2),
+            PxTextSection::new("cd\nef"),
+        ], Handle::default());
+        let layout = layout_text(&text, &font, None);
+
+        let positions: Vec<UVec2> = layout.glyphs.iter().map(|g| g.pos).collect();
+        assert_eq!(positions, [
+            UVec2::new(0, 0),
+            UVec2::new(8, 0),
+            UVec2::new(16, 3),
+            UVec2::new(20, 3),
+            UVec2::new(0, 9),
+            UVec2::new(4, 9),
+        ]);
+        assert_eq!(layout.glyphs[0].size, UVec2::new(6, 8));
+        assert_eq!(layout.size, UVec2::new(23, 13));
+    }
+
+    #[test]
+    fn fit_picks_largest_scale() {
+        let font = test_font();
+        let text = text("ab cd", WrapMode::WrapWord, UVec2::new(20, 20))
+            .with_fit(FitMode::Scale(4));
+        let fitted = fit_text(&text, None, |_| FontLoad::Loaded(&font)).unwrap();
+
+        assert_eq!(fitted.scale, 2);
+        assert_eq!(lines(&fitted.layout), ["ab ", "cd"]);
+        assert!(!fitted.layout.overflowed);
+
+        let text = text.with_bounding_box(UVec2::new(2, 2));
+        let fitted = fit_text(&text, None, |_| FontLoad::Loaded(&font)).unwrap();
+        assert_eq!(fitted.scale, 1);
+        assert!(fitted.layout.overflowed);
+    }
+
+    #[test]
+    fn fit_falls_back_to_fonts() {
+        let (mut large, small) = (test_font(), test_font());
+        for glyph in large.char_map.values_mut() {
+            glyph.src_rect.max.x += 4;
+        }
+        let small_handle = Handle::weak_from_u128(1);
+        let text = text("abc", WrapMode::Truncate, UVec2::new(12, 4))
+            .with_fit(FitMode::Fonts(vec![small_handle.clone()]));
+        let fitted = fit_text(&text, None, |handle| match *handle == small_handle {
+            true => FontLoad::Loaded(&small),
+            false => FontLoad::Loaded(&large),
+        }).unwrap();
+
+        assert_eq!(fitted.text.font, small_handle);
+        assert_eq!(lines(&fitted.layout), ["abc"]);
+    }
+
+    #[test]
+    fn fit_waits_for_fonts_that_are_loading() {
+        let font = test_font();
+        let fallback = Handle::weak_from_u128(1);
+        let text = text("abc", WrapMode::Truncate, UVec2::new(12, 4))
+            .with_fit(FitMode::Fonts(vec![fallback.clone()]));
+        let fit = |primary: fn(&PxFont) -> FontLoad| fit_text(&text, None, |handle| match *handle == fallback {
+            true => FontLoad::Loaded(&font),
+            false => primary(&font),
+        });
+
+        // The fallback is only picked once the primary font failed
+        assert!(fit(|_| FontLoad::Loading).is_none());
+        assert_eq!(fit(|_| FontLoad::Failed).unwrap().text.font, fallback);
+        assert_eq!(fit(|font| FontLoad::Loaded(font)).unwrap().text.font, text.font);
+    }
+
+    #[test]
+    fn pages_split_between_lines() {
+        let font = test_font();
+        let text = text("ab cd ef gh ij", WrapMode::WrapWord, UVec2::new(8, 9));
+
+        let layout = layout_text(&text, &font, Some(1));
+        assert_eq!(lines(&layout), ["ef", "gh"]);
+        assert_eq!(layout.glyphs[0].pos, UVec2::ZERO);
+        assert_eq!(layout.glyphs[2].pos, UVec2::new(0, 5));
+        assert_eq!(layout.pages, 3);
+        assert_eq!(layout.page, (1, 6..12));
+        assert!(!layout.overflowed);
+
+        let layout = layout_text(&text, &font, Some(9));
+        assert_eq!(lines(&layout), ["ij"]);
+        assert_eq!(layout.page, (2, 12..14));
+    }
+}
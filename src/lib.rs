@@ -1,5 +1,16 @@
 mod input;
+mod layout;
+mod pages;
+#[cfg(feature = "3d")]
+mod render_3d;
+mod render_mesh;
 mod render_text;
+#[cfg(feature = "ui")]
+mod render_ui;
+mod snap;
+#[cfg(test)]
+mod test_support;
+mod typewriter;
 pub mod pxfontdata;
 pub mod plugin;
 pub mod pxfont;
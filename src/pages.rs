@@ -0,0 +1,34 @@
+use bevy::prelude::*;
+
+use crate::pxtext::{PxText, PxTextLastPage, PxTextLayoutInfo, PxTextPages};
+
+/// Keeps each `PxTextPages` in step with the pages its text was laid out with,
+/// sending `PxTextLastPage` when the last page is shown for the first time since its text changed.
+/// Changing the text goes back to the first page.
+#[allow(clippy::type_complexity)]
+pub(crate) fn text_pages_system(
+    mut q_text: Query<(Entity, Ref<PxText>, Option<Ref<PxTextLayoutInfo>>, &mut PxTextPages)>,
+    mut last_page_evw: EventWriter<PxTextLastPage>,
+) {
+    for (entity, text, info, mut pages) in &mut q_text {
+        if text.is_changed() && !text.is_added() {
+            if pages.page != 0 {
+                pages.page = 0;
+            }
+            pages.bypass_change_detection().on_last = false;
+        }
+        let Some(info) = info.filter(|info| info.is_changed()) else {
+            continue;
+        };
+        // The layout already shows the last page when the page is past it
+        let pages = pages.bypass_change_detection();
+        pages.count = (info.page_count as usize).max(1);
+        pages.page = pages.page.min(pages.count - 1);
+
+        let on_last = pages.is_last_page();
+        if on_last && !pages.on_last {
+            last_page_evw.send(PxTextLastPage { entity });
+        }
+        pages.on_last = on_last;
+    }
+}
@@ -1,6 +1,10 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, render::view::VisibilitySystems};
 
-use crate::{input::handle_input_system, pxfont::{PxFont, PxFontLoader}, pxtext::PxTextEvent, render_text::{prepare_text_system, render_text_system}};
+#[cfg(feature = "3d")]
+use crate::render_3d::{billboard_system, render_3d_system};
+#[cfg(feature = "ui")]
+use crate::render_ui::{measure_text_node_system, render_text_node_system};
+use crate::{input::handle_input_system, pages::text_pages_system, pxfont::{PxFont, PxFontLoader}, pxtext::{PxTextEvent, PxTextLastPage, PxTypewriterChar, PxTypewriterFinished}, render_mesh::render_mesh_system, snap::snap_text_system, typewriter::typewriter_system, render_text::{cleanup_text_system, font_load_failed_system, prepare_text_system, render_text_system}};
 
 #[derive(Default)]
 pub struct PxtxtPlugin;
@@ -9,12 +13,35 @@ impl Plugin for PxtxtPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app
             .add_event::<PxTextEvent>()
+            .add_event::<PxTypewriterChar>()
+            .add_event::<PxTypewriterFinished>()
+            .add_event::<PxTextLastPage>()
             .init_asset::<PxFont>()
             .init_asset_loader::<PxFontLoader>()
             .add_systems(Update, (
                 prepare_text_system,
                 handle_input_system,
+                typewriter_system.after(text_pages_system),
+                text_pages_system,
             ))
-            .add_systems(PostUpdate, render_text_system);
+            .add_systems(PostUpdate, (
+                render_text_system,
+                render_mesh_system.before(VisibilitySystems::CalculateBounds),
+                cleanup_text_system,
+                font_load_failed_system,
+                snap_text_system.after(TransformSystem::TransformPropagate),
+            ));
+
+        #[cfg(feature = "3d")]
+        app.add_systems(PostUpdate, (
+            render_3d_system.after(render_text_system).before(VisibilitySystems::CalculateBounds),
+            billboard_system.after(TransformSystem::TransformPropagate),
+        ));
+
+        #[cfg(feature = "ui")]
+        app.add_systems(PostUpdate, (
+            measure_text_node_system.before(bevy::ui::UiSystem::Layout),
+            render_text_node_system.after(bevy::ui::UiSystem::Layout),
+        ));
     }
 }
@@ -1,11 +1,11 @@
 use std::{fs::File, io::BufReader, path::PathBuf};
 
 use ahash::AHashMap;
-use bevy::{asset::{Asset, AssetLoader, AsyncReadExt}, math::URect, reflect::TypePath};
-use image::{ImageFormat, Rgba, RgbaImage};
+use bevy::{asset::{Asset, AssetLoader, AsyncReadExt, Handle}, math::{URect, UVec2}, reflect::TypePath, render::{render_asset::RenderAssetUsages, render_resource::{Extent3d, TextureDimension, TextureFormat}, texture::{Image, ImageSampler}}};
+use image::{imageops, ImageFormat, Rgba, RgbaImage};
 use thiserror::Error;
 
-use crate::pxfontdata::{GlyphWidth, PxFontData};
+use crate::{layout::{fit_text, FontLoad}, pxfontdata::{GlyphWidth, PxFontData}, pxtext::{PxText, PxTextSection}};
 
 #[derive(Debug, Clone)]
 pub(crate) struct PxGlyph {
@@ -16,10 +16,81 @@ pub(crate) struct PxGlyph {
 pub struct PxFont {
     pub(crate) name: String,
     pub(crate) source: RgbaImage,
+    /// The source image on the GPU, with an extra row whose first pixel is white
+    /// for drawing underlines
+    pub(crate) atlas: Handle<Image>,
     pub(crate) char_map: AHashMap<char, PxGlyph>,
     pub(crate) ascender: u32,
     pub(crate) descender: u32,
     pub(crate) spacing: u32,
+    pub(crate) vertical_advance: u32,
+    pub(crate) space_width: Option<u32>,
+}
+
+impl PxFont {
+    /// Measures the lines `text` would take up in this font, using the settings of
+    /// `options` and the style of its first section. The bounding box only sets the
+    /// width to wrap at, so the size can be smaller or larger than it.
+    pub fn measure(&self, text: &str, options: &PxText) -> UVec2 {
+        let options = self.measured_text(text, options);
+        fit_text(&options, None, |_| FontLoad::Loaded(self))
+            .map(|fitted| (fitted.layout.content.as_vec2() * fitted.layout.scale).ceil().as_uvec2())
+            .unwrap_or_default()
+    }
+
+    /// Measures each line `text` would be wrapped into in this font, from the start of
+    /// the line to the end of its last visible glyph, before any shrinking to fit
+    pub fn measure_lines(&self, text: &str, options: &PxText) -> Vec<UVec2> {
+        let options = self.measured_text(text, options);
+        let Some(fitted) = fit_text(&options, None, |_| FontLoad::Loaded(self)) else {
+            return Vec::new();
+        };
+        let layout = fitted.layout;
+
+        layout.lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let glyphs = layout.glyphs
+                    .iter()
+                    .filter(|glyph| glyph.line == i && !glyph.c.is_whitespace());
+                match layout.vertical {
+                    true => UVec2::new(
+                        line.rect.width(),
+                        glyphs.map(|g| g.pos.y + g.size.y).max().unwrap_or_default(),
+                    ),
+                    false => UVec2::new(
+                        glyphs.map(|g| g.pos.x + g.size.x).max().unwrap_or_default(),
+                        line.rect.height(),
+                    ),
+                }
+            })
+            .collect()
+    }
+
+    /// Copies everything needed to lay out text, leaving out the source image
+    #[cfg(feature = "ui")]
+    pub(crate) fn metrics(&self) -> PxFont {
+        PxFont {
+            name: self.name.clone(),
+            source: RgbaImage::default(),
+            atlas: self.atlas.clone(),
+            char_map: self.char_map.clone(),
+            ascender: self.ascender,
+            descender: self.descender,
+            spacing: self.spacing,
+            vertical_advance: self.vertical_advance,
+            space_width: self.space_width,
+        }
+    }
+
+    fn measured_text(&self, text: &str, options: &PxText) -> PxText {
+        let section = PxTextSection {
+            value: text.to_owned(),
+            ..options.sections.first().cloned().unwrap_or_default()
+        };
+        PxText { sections: vec![section], ..options.clone() }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -52,7 +123,7 @@ impl AssetLoader for PxFontLoader {
             &'a self,
             reader: &'a mut bevy::asset::io::Reader,
             _settings: &'a Self::Settings,
-            _load_context: &'a mut bevy::asset::LoadContext,
+            load_context: &'a mut bevy::asset::LoadContext,
         ) -> impl bevy::utils::ConditionalSendFuture<Output = Result<Self::Asset, Self::Error>> {
         Box::pin(async move {
             let mut bytes = Vec::new();
@@ -145,13 +216,34 @@ impl AssetLoader for PxFontLoader {
                 }
             }
 
+            let mut atlas = RgbaImage::new(source.width(), source.height() + 1);
+            imageops::replace(&mut atlas, &source, 0, 0);
+            atlas.put_pixel(0, source.height(), Rgba::from([255, 255, 255, 255]));
+            let mut atlas = Image::new(
+                Extent3d {
+                    width: atlas.width(),
+                    height: atlas.height(),
+                    depth_or_array_layers: 1,
+                },
+                TextureDimension::D2,
+                atlas.into_vec(),
+                TextureFormat::Rgba8UnormSrgb,
+                RenderAssetUsages::RENDER_WORLD,
+            );
+            atlas.sampler = ImageSampler::nearest();
+            let atlas = load_context.add_labeled_asset("atlas".into(), atlas);
+
             Ok(PxFont {
                 name: data.name,
                 source,
+                atlas,
                 char_map,
                 ascender: data.ascender,
                 descender: data.descender,
                 spacing: data.spacing,
+                vertical_advance: data.vertical_advance
+                    .unwrap_or(data.ascender + data.descender + data.spacing),
+                space_width: data.space_width,
             })
         })
     }
@@ -160,3 +252,25 @@ impl AssetLoader for PxFontLoader {
         &["ron"]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pxtext::WrapMode, test_support::test_font};
+
+    #[test]
+    fn measure_matches_layout() {
+        let font = test_font();
+        let mut options = PxText::from_section("", Handle::default())
+            .with_bounding_box(UVec2::new(14, 20));
+        options.wrap_mode = WrapMode::WrapWord;
+
+        // The size of the wrapped lines, not the bounding box they wrap in
+        assert_eq!(font.measure("ab cd efg", &options), UVec2::new(11, 14));
+        assert_eq!(font.measure_lines("ab cd efg", &options), [
+            UVec2::new(7, 4),
+            UVec2::new(7, 4),
+            UVec2::new(11, 4),
+        ]);
+    }
+}
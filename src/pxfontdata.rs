@@ -18,6 +18,12 @@ pub struct PxFontData {
     pub spacing: u32,
     #[serde(default = "zerozero")]
     pub padding: (u32, u32),
+    /// Distance between the tops of glyphs in vertical text
+    #[serde(default)]
+    pub vertical_advance: Option<u32>,
+    /// Width of a space, used instead of the space glyph if there is one
+    #[serde(default)]
+    pub space_width: Option<u32>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -46,7 +52,7 @@ impl CharLayout {
     pub fn get(&self, index: u32) -> Option<char> {
         match &self {
             Self::StartingAt(c) => {
-                let code: u32 = (*c).into();
+                let code: u32 = *c as u32;
                 let new = code + index;
                 char::from_u32(new)
             }
@@ -61,9 +67,9 @@ impl IntoIterator for CharLayout {
 
     fn into_iter(self) -> Self::IntoIter {
         let idx = match &self {
-            CharLayout::StartingAt(c) => (*c).into(),
-            CharLayout::Ranges(vec) => (*vec[0].start()).into(),
-            CharLayout::Listed(vec) => vec[0].into(),
+            CharLayout::StartingAt(c) => *c,
+            CharLayout::Ranges(vec) => *vec[0].start(),
+            CharLayout::Listed(vec) => vec[0],
         };
 
         CharLayoutIter {
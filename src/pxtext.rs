@@ -1,6 +1,6 @@
 use std::ops::Range;
 
-use bevy::prelude::*;
+use bevy::{asset::AssetPath, color::Mix, prelude::*};
 
 use crate::pxfont::PxFont;
 
@@ -16,12 +16,301 @@ pub struct PxTextBundle {
     pub view_visibility: ViewVisibility,
 }
 
+/// Text drawn straight from the font's texture, with a quad for each glyph,
+/// instead of being rendered to a new image whenever it changes.
+/// This suits text that changes every frame, like scores and timers.
+#[derive(Debug, Bundle, Clone, Default)]
+pub struct PxTextMeshBundle {
+    pub text: PxText,
+    pub mesh: PxTextMesh,
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
+    pub visibility: Visibility,
+    pub inherited_visibility: InheritedVisibility,
+    pub view_visibility: ViewVisibility,
+}
+
+/// Marks a `PxText` to be drawn as a mesh of glyph quads rather than as an image
+#[derive(Debug, Component, Clone, Copy, Default)]
+pub struct PxTextMesh;
+
+/// Text in a `bevy_ui` layout, sized from the text and wrapped to the node's width.
+/// The text is drawn at the largest whole multiple of its pixels that the UI scale allows.
+#[cfg(feature = "ui")]
+#[derive(Debug, Bundle, Default)]
+pub struct PxTextNodeBundle {
+    pub text: PxText,
+    pub marker: PxTextNode,
+    pub node: Node,
+    pub style: Style,
+    pub calculated_size: bevy::ui::ContentSize,
+    pub image: UiImage,
+    pub background_color: BackgroundColor,
+    pub focus_policy: bevy::ui::FocusPolicy,
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
+    pub visibility: Visibility,
+    pub inherited_visibility: InheritedVisibility,
+    pub view_visibility: ViewVisibility,
+    pub z_index: ZIndex,
+}
+
+/// Marks a `PxText` to be measured and drawn as a UI node
+#[cfg(feature = "ui")]
+#[derive(Debug, Component, Clone, Copy, Default)]
+pub struct PxTextNode;
+
+/// Text in a 3D world, drawn on an unlit quad facing along the entity's Z axis
+#[cfg(feature = "3d")]
+#[derive(Debug, Bundle, Clone, Default)]
+pub struct PxText3dBundle {
+    pub text: PxText,
+    pub world: PxText3d,
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
+    pub visibility: Visibility,
+    pub inherited_visibility: InheritedVisibility,
+    pub view_visibility: ViewVisibility,
+}
+
+/// How a `PxText` is placed in a 3D world
+#[cfg(feature = "3d")]
+#[derive(Debug, Component, Clone, Copy)]
+pub struct PxText3d {
+    /// How many pixels of text fit in one world unit
+    pub pixels_per_unit: f32,
+    /// Whether the text always faces the camera
+    pub billboard: bool,
+    /// How the quad blends with what's behind it. Blending keeps translucent colors,
+    /// while masking sorts like opaque geometry.
+    pub alpha_mode: AlphaMode,
+}
+
+#[cfg(feature = "3d")]
+impl Default for PxText3d {
+    fn default() -> Self {
+        Self::new(16.)
+    }
+}
+
+#[cfg(feature = "3d")]
+impl PxText3d {
+    pub fn new(pixels_per_unit: f32) -> Self {
+        Self {
+            pixels_per_unit,
+            billboard: false,
+            alpha_mode: AlphaMode::Blend,
+        }
+    }
+
+    pub fn billboarded(mut self) -> Self {
+        self.billboard = true;
+        self
+    }
+
+    pub fn with_alpha_mode(mut self, alpha_mode: AlphaMode) -> Self {
+        self.alpha_mode = alpha_mode;
+        self
+    }
+}
+
+/// Snaps the rendered text to the pixel grid of the active 2D camera, so that
+/// nearest-sampled glyphs don't shimmer as the text or the camera moves
+#[derive(Debug, Component, Clone, Copy, Default)]
+pub struct PxTextSnap {
+    /// The snapped position last frame and how far it was moved, so that an unchanged
+    /// transform isn't snapped twice
+    pub(crate) last: Option<(Vec3, Vec3)>,
+}
+
+/// Reveals text a character at a time. The text is laid out in full from the start,
+/// so words don't jump to the next line as they're revealed.
+/// Changing the `PxText` starts the reveal over. With `PxTextPages`, each page is revealed in turn.
+#[derive(Debug, Component, Clone)]
+pub struct PxTypewriter {
+    /// Characters revealed per second
+    pub speed: f32,
+    /// Extra seconds to wait after revealing each of these characters
+    pub pauses: Vec<(char, f32)>,
+    pub(crate) revealed: usize,
+    /// Seconds until the next character is revealed
+    pub(crate) wait: f32,
+    pub(crate) skipped: bool,
+    pub(crate) finished: bool,
+}
+
+impl Default for PxTypewriter {
+    fn default() -> Self {
+        Self::new(30.)
+    }
+}
+
+impl PxTypewriter {
+    pub fn new(speed: f32) -> Self {
+        Self {
+            speed,
+            pauses: Vec::new(),
+            revealed: 0,
+            wait: 0.,
+            skipped: false,
+            finished: false,
+        }
+    }
+
+    /// Waits `seconds` longer after each of `chars`, such as ".,!?"
+    pub fn with_pause(mut self, chars: &str, seconds: f32) -> Self {
+        self.pauses.extend(chars.chars().map(|c| (c, seconds)));
+        self
+    }
+
+    /// Reveals the rest of the text on the next update, without a `PxTypewriterChar` for each
+    pub fn skip(&mut self) {
+        self.skipped = true;
+    }
+
+    /// Hides the text to reveal it again
+    pub fn restart(&mut self) {
+        self.revealed = 0;
+        self.wait = 0.;
+        self.skipped = false;
+        self.finished = false;
+    }
+
+    /// How many characters are shown
+    pub fn revealed(&self) -> usize {
+        self.revealed
+    }
+
+    /// Whether the whole text, or the whole page with `PxTextPages`, is shown
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Seconds to wait after revealing `c`
+    pub(crate) fn delay(&self, c: char) -> f32 {
+        let pause = self.pauses
+            .iter()
+            .filter(|(paused, _)| *paused == c)
+            .map(|(_, seconds)| seconds)
+            .sum::<f32>();
+        1. / self.speed + pause
+    }
+}
+
+/// A `PxTypewriter` revealed a character
+#[derive(Event, Debug)]
+pub struct PxTypewriterChar {
+    /// The entity with the `PxText` component
+    pub entity: Entity,
+    /// Index of the character across all sections
+    pub index: usize,
+    pub char: char,
+}
+
+/// A `PxTypewriter` revealed the last of its text
+#[derive(Event, Debug)]
+pub struct PxTypewriterFinished {
+    /// The entity with the `PxText` component
+    pub entity: Entity,
+}
+
+/// Splits text that doesn't fit in its bounding box into pages, showing one page at a time.
+/// Pages break between lines, each holding as many as fit in the box or `max_lines`.
+/// Changing the `PxText` goes back to the first page.
+#[derive(Debug, Component, Clone)]
+pub struct PxTextPages {
+    pub(crate) page: usize,
+    /// Number of pages the last layout had
+    pub(crate) count: usize,
+    /// Whether the last page was shown last time the text was laid out
+    pub(crate) on_last: bool,
+}
+
+impl Default for PxTextPages {
+    fn default() -> Self {
+        Self {
+            page: 0,
+            count: 1,
+            on_last: false,
+        }
+    }
+}
+
+impl PxTextPages {
+    /// Index of the page shown
+    pub fn page(&self) -> usize {
+        self.page
+    }
+
+    /// How many pages the text was split into when it was last laid out
+    pub fn page_count(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_last_page(&self) -> bool {
+        self.page + 1 >= self.count
+    }
+
+    /// Shows the page after this one, returning `false` if this is the last page
+    pub fn next_page(&mut self) -> bool {
+        if self.is_last_page() {
+            return false;
+        }
+        self.page += 1;
+        true
+    }
+
+    /// Shows the page before this one, returning `false` if this is the first page
+    pub fn previous_page(&mut self) -> bool {
+        if self.page == 0 {
+            return false;
+        }
+        self.page -= 1;
+        true
+    }
+
+    /// Shows the page at `page`, or the last page if there aren't that many
+    pub fn set_page(&mut self, page: usize) {
+        self.page = page;
+    }
+}
+
+/// The last page of a `PxTextPages` was shown
+#[derive(Event, Debug)]
+pub struct PxTextLastPage {
+    /// The entity with the `PxText` component
+    pub entity: Entity,
+}
+
 /// A section of formatted text
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct PxTextSection {
     pub value: String,
     pub color: Color,
-    pub underline: bool,
+    pub underline: Option<TextDecoration>,
+    pub strikethrough: Option<TextDecoration>,
+    pub overline: Option<TextDecoration>,
+    /// Color filling the section's lines behind its glyphs
+    pub background: Option<Color>,
+    /// How far the background reaches past the glyphs on each side
+    pub background_padding: u32,
+    /// Motion or color that changes every frame
+    pub effect: Option<TextEffect>,
+    /// Overrides `PxText::line_spacing` below lines that end in this section
+    pub line_spacing: Option<i32>,
+    /// Overrides the font's spacing between glyphs, which can be negative for tighter text
+    pub letter_spacing: Option<i32>,
+    /// Size of each of the font's pixels in the text, which keeps the pixels crisp.
+    /// A scale of 0 is laid out as 1.
+    pub scale: u32,
+    /// Colors blended across the section in place of `color`
+    pub gradient: Option<TextGradient>,
+}
+
+impl Default for PxTextSection {
+    fn default() -> Self {
+        Self::new("")
+    }
 }
 
 impl PxTextSection {
@@ -29,7 +318,16 @@ impl PxTextSection {
         Self {
             value: value.into(),
             color: Color::WHITE,
-            underline: false,
+            underline: None,
+            strikethrough: None,
+            overline: None,
+            background: None,
+            background_padding: 0,
+            effect: None,
+            line_spacing: None,
+            letter_spacing: None,
+            scale: 1,
+            gradient: None,
         }
     }
 
@@ -39,7 +337,53 @@ impl PxTextSection {
     }
 
     pub fn underlined(mut self) -> Self {
-        self.underline = true;
+        self.underline = Some(TextDecoration::default());
+        self
+    }
+
+    pub fn with_underline(mut self, underline: TextDecoration) -> Self {
+        self.underline = Some(underline);
+        self
+    }
+
+    pub fn with_strikethrough(mut self, strikethrough: TextDecoration) -> Self {
+        self.strikethrough = Some(strikethrough);
+        self
+    }
+
+    pub fn with_overline(mut self, overline: TextDecoration) -> Self {
+        self.overline = Some(overline);
+        self
+    }
+
+    pub fn with_background(mut self, color: Color, padding: u32) -> Self {
+        self.background = Some(color);
+        self.background_padding = padding;
+        self
+    }
+
+    pub fn with_effect(mut self, effect: TextEffect) -> Self {
+        self.effect = Some(effect);
+        self
+    }
+
+    pub fn with_gradient(mut self, gradient: TextGradient) -> Self {
+        self.gradient = Some(gradient);
+        self
+    }
+
+    pub fn with_line_spacing(mut self, spacing: i32) -> Self {
+        self.line_spacing = Some(spacing);
+        self
+    }
+
+    pub fn with_letter_spacing(mut self, spacing: i32) -> Self {
+        self.letter_spacing = Some(spacing);
+        self
+    }
+
+    pub fn with_scale(mut self, scale: u32) -> Self {
+        self.scale = scale.max(1);
         self
     }
 }
@@ -52,17 +396,281 @@ pub enum WrapMode {
     WrapWord,
     /// Break off words in the middle
     WrapChar,
-    /// Don't draw characters out of bounds
+    /// Break wherever the Unicode line breaking rules (UAX #14) allow,
+    /// which also handles text without spaces, like Chinese and Japanese
+    WrapUnicode,
+    /// Don't wrap, cutting each line off at the bounds
     Truncate,
+    /// Don't wrap, growing the image to fit the longest line
+    NoWrap,
+}
+
+/// Where words can be hyphenated when wrapping them with `WrapMode::WrapWord` or `WrapMode::WrapUnicode`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Hyphenation {
+    /// Only at soft hyphens (U+00AD), which are drawn as `-` when the line breaks there
+    #[default]
+    Manual,
+    /// At soft hyphens and wherever the language's hyphenation patterns allow
+    #[cfg(feature = "hyphenation")]
+    Auto(hypher::Lang),
+}
+
+/// Direction that text is laid out in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextDirection {
+    /// Always lay out characters left to right, in the order they're written
+    #[default]
+    LeftToRight,
+    /// Reorder right to left runs with the Unicode bidi algorithm, taking each
+    /// paragraph's direction from its first strong character
+    Auto,
+    /// Reorder with the Unicode bidi algorithm in right to left paragraphs
+    RightToLeft,
+}
+
+/// Which way lines of text run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WritingMode {
+    /// Glyphs advance left to right, and lines go top to bottom
+    #[default]
+    HorizontalTb,
+    /// Glyphs advance top to bottom, and columns go right to left
+    VerticalRl,
+    /// Glyphs advance top to bottom, and columns go left to right
+    VerticalLr,
+}
+
+/// Where tabs advance to, in pixels from the start of the line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TabStops {
+    /// Left aligned stops at every multiple of the interval
+    Interval(u32),
+    /// Stops at the given positions, in ascending order.
+    /// Past the last stop, a tab is as wide as a space.
+    Positions(Vec<TabStop>),
+}
+
+impl Default for TabStops {
+    fn default() -> Self {
+        Self::Interval(16)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TabStop {
+    pub position: u32,
+    pub align: TabAlign,
+}
+
+impl TabStop {
+    pub fn new(position: u32, align: TabAlign) -> Self {
+        Self {
+            position,
+            align,
+        }
+    }
+}
+
+/// How the text after a tab lines up with its tab stop
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TabAlign {
+    /// The text starts at the stop
+    #[default]
+    Left,
+    /// The text ends at the stop
+    Right,
+    /// The first `.` in the text starts at the stop, for lining up numbers
+    Decimal,
+}
+
+/// Motion or color that changes over time, applied to each glyph of a section.
+/// Distances are in the font's pixels and speeds are in cycles per second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextEffect {
+    /// Glyphs bob up and down one after another
+    Wave { amplitude: f32, speed: f32 },
+    /// Glyphs jump around at random
+    Shake { amplitude: f32 },
+    /// Glyphs cycle through every hue, each a little ahead of the last
+    Rainbow { speed: f32 },
+    /// Glyphs fade to half their opacity and back
+    Pulse { speed: f32 },
+}
+
+/// A line drawn under, through or over a section of text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextDecoration {
+    /// Color of the line, or the section's color if `None`
+    pub color: Option<Color>,
+    /// Thickness of the line in the font's pixels
+    pub thickness: u32,
+    /// How many of the font's pixels the line is moved down from its usual place
+    pub offset: i32,
+    pub style: DecorationStyle,
+}
+
+impl Default for TextDecoration {
+    fn default() -> Self {
+        Self {
+            color: None,
+            thickness: 1,
+            offset: 0,
+            style: DecorationStyle::Solid,
+        }
+    }
+}
+
+impl TextDecoration {
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    pub fn with_thickness(mut self, thickness: u32) -> Self {
+        self.thickness = thickness;
+        self
+    }
+
+    pub fn with_offset(mut self, offset: i32) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_style(mut self, style: DecorationStyle) -> Self {
+        self.style = style;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecorationStyle {
+    #[default]
+    Solid,
+    /// Every other pixel
+    Dotted,
+    /// Three pixels on, two off
+    Dashed,
+    /// Rises and falls by a pixel
+    Wavy,
+}
+
+/// Colors blended across a section of text
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextGradient {
+    /// Colors evenly spread from the start of the gradient to its end
+    pub stops: Vec<Color>,
+    pub direction: GradientDirection,
+    /// How many flat bands of color the gradient is split into, or `None` for a smooth blend
+    pub steps: Option<u32>,
+}
+
+impl TextGradient {
+    /// Blends from the left of the section to its right
+    pub fn horizontal(stops: impl Into<Vec<Color>>) -> Self {
+        Self::new(stops, GradientDirection::Horizontal)
+    }
+
+    /// Blends from the top of each line to its bottom, across every glyph on the line whatever its scale
+    pub fn vertical(stops: impl Into<Vec<Color>>) -> Self {
+        Self::new(stops, GradientDirection::Vertical)
+    }
+
+    /// Gives each character one color, blending from the first character to the last
+    pub fn per_char(stops: impl Into<Vec<Color>>) -> Self {
+        Self::new(stops, GradientDirection::PerChar)
+    }
+
+    fn new(stops: impl Into<Vec<Color>>, direction: GradientDirection) -> Self {
+        Self {
+            stops: stops.into(),
+            direction,
+            steps: None,
+        }
+    }
+
+    pub fn with_steps(mut self, steps: u32) -> Self {
+        self.steps = Some(steps);
+        self
+    }
+
+    /// Finds the color `t` of the way through the gradient
+    pub(crate) fn sample(&self, t: f32) -> Srgba {
+        let t = match self.steps {
+            Some(steps) if steps > 1 => {
+                (t * steps as f32).floor().min(steps as f32 - 1.) / (steps - 1) as f32
+            }
+            Some(_) => 0.,
+            None => t,
+        };
+
+        match self.stops.as_slice() {
+            [] => Srgba::WHITE,
+            [color] => color.to_srgba(),
+            stops => {
+                let x = t.clamp(0., 1.) * (stops.len() - 1) as f32;
+                let i = (x as usize).min(stops.len() - 2);
+                stops[i].to_srgba().mix(&stops[i + 1].to_srgba(), x - i as f32)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GradientDirection {
+    Horizontal,
+    Vertical,
+    PerChar,
+}
+
+/// What to do with text that doesn't fit in the bounding box
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowMode {
+    /// Leave out whatever doesn't fit
+    #[default]
+    Clip,
+    /// Replace the end of the text with the font's `…`, or `...` if it has none
+    Ellipsis,
+    /// Scale the whole text down until it fits
+    ShrinkToFit,
+}
+
+/// How to pick the size or font of text so that it fits in the bounding box
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum FitMode {
+    /// Always use the text's own font and scale
+    #[default]
+    None,
+    /// Scale every section by the largest factor up to this one at which the text fits,
+    /// or by 1 if it never does
+    Scale(u32),
+    /// Use the text's own font if the text fits, otherwise the first of these fonts
+    /// in which it does, or the last one if it never does
+    Fonts(Vec<Handle<PxFont>>),
 }
 
 #[derive(Debug, Component, Clone, Default)]
 pub struct PxText {
     pub sections: Vec<PxTextSection>,
     pub font: Handle<PxFont>,
-    pub line_spacing: u32,
+    /// Space between lines, which can be negative for fonts with tall ascenders
+    pub line_spacing: i32,
+    /// Extra space after each paragraph, on top of the line spacing
+    pub paragraph_spacing: u32,
+    /// Indent of the first line in each paragraph
+    pub first_line_indent: u32,
+    /// Indent of every line after the first in each paragraph
+    pub hanging_indent: u32,
     pub wrap_mode: WrapMode,
+    pub hyphenation: Hyphenation,
+    pub direction: TextDirection,
+    pub writing_mode: WritingMode,
+    pub tab_stops: TabStops,
+    pub overflow: OverflowMode,
+    pub fit: FitMode,
     pub bounding_box: Option<UVec2>,
+    /// The most lines to show, no matter how much room the bounding box has
+    pub max_lines: Option<u32>,
 }
 
 impl PxText {
@@ -71,8 +679,18 @@ impl PxText {
             sections: vec![PxTextSection::new(value)],
             font,
             line_spacing: 1,
+            paragraph_spacing: 0,
+            first_line_indent: 0,
+            hanging_indent: 0,
             wrap_mode: WrapMode::default(),
+            hyphenation: Hyphenation::default(),
+            direction: TextDirection::default(),
+            writing_mode: WritingMode::default(),
+            tab_stops: TabStops::default(),
+            overflow: OverflowMode::default(),
+            fit: FitMode::default(),
             bounding_box: None,
+            max_lines: None,
         }
     }
 
@@ -81,16 +699,37 @@ impl PxText {
             sections,
             font,
             line_spacing: 1,
+            paragraph_spacing: 0,
+            first_line_indent: 0,
+            hanging_indent: 0,
             wrap_mode: WrapMode::default(),
+            hyphenation: Hyphenation::default(),
+            direction: TextDirection::default(),
+            writing_mode: WritingMode::default(),
+            tab_stops: TabStops::default(),
+            overflow: OverflowMode::default(),
+            fit: FitMode::default(),
             bounding_box: None,
+            max_lines: None,
         }
     }
 
-    pub fn with_line_spacing(mut self, spacing: u32) -> Self {
+    pub fn with_line_spacing(mut self, spacing: i32) -> Self {
         self.line_spacing = spacing;
         self
     }
 
+    pub fn with_paragraph_spacing(mut self, spacing: u32) -> Self {
+        self.paragraph_spacing = spacing;
+        self
+    }
+
+    pub fn with_indents(mut self, first_line: u32, hanging: u32) -> Self {
+        self.first_line_indent = first_line;
+        self.hanging_indent = hanging;
+        self
+    }
+
     pub fn with_truncating_wrap(mut self) -> Self {
         self.wrap_mode = WrapMode::Truncate;
         self
@@ -106,10 +745,89 @@ impl PxText {
         self
     }
 
+    pub fn with_unicode_wrap(mut self) -> Self {
+        self.wrap_mode = WrapMode::WrapUnicode;
+        self
+    }
+
+    pub fn with_no_wrap(mut self) -> Self {
+        self.wrap_mode = WrapMode::NoWrap;
+        self
+    }
+
+    pub fn with_hyphenation(mut self, hyphenation: Hyphenation) -> Self {
+        self.hyphenation = hyphenation;
+        self
+    }
+
+    pub fn with_direction(mut self, direction: TextDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    pub fn with_writing_mode(mut self, writing_mode: WritingMode) -> Self {
+        self.writing_mode = writing_mode;
+        self
+    }
+
+    pub fn with_tab_stops(mut self, tab_stops: TabStops) -> Self {
+        self.tab_stops = tab_stops;
+        self
+    }
+
     pub fn with_bounding_box(mut self, bounds: UVec2) -> Self {
         self.bounding_box = Some(bounds);
         self
     }
+
+    pub fn with_max_lines(mut self, max_lines: u32) -> Self {
+        self.max_lines = Some(max_lines);
+        self
+    }
+
+    pub fn with_overflow(mut self, overflow: OverflowMode) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    pub fn with_fit(mut self, fit: FitMode) -> Self {
+        self.fit = fit;
+        self
+    }
+}
+
+/// Information about the last time a `PxText` was rendered, added to the same entity
+#[derive(Debug, Component, Clone, Default)]
+pub struct PxTextLayoutInfo {
+    /// Size of the rendered image in pixels
+    pub size: UVec2,
+    /// Size the lines take up before any shrinking, which may be smaller or larger than the image
+    pub content_size: UVec2,
+    /// Number of lines shown, after wrapping and clamping to `max_lines`
+    pub line_count: u32,
+    /// Number of pages the text splits into with `PxTextPages`
+    pub page_count: u32,
+    /// Index of the page shown, which is 0 without `PxTextPages`
+    pub page: u32,
+    /// Indices of the characters on the page shown, across all sections
+    pub page_chars: Range<usize>,
+    /// Whether the text didn't fit in the bounding box.
+    /// With `OverflowMode::ShrinkToFit`, whether the text had to be shrunk.
+    pub overflowed: bool,
+    /// Factor every section was scaled by to fit the text, as chosen by `FitMode::Scale`
+    pub scale: u32,
+    /// Font the text was rendered with, as chosen by `FitMode::Fonts`
+    pub font: Handle<PxFont>,
+}
+
+/// Added to a `PxText` when its font, or one of its `FitMode::Fonts` fallbacks, failed to load.
+/// Removed once the text renders with another of its fonts.
+#[derive(Debug, Component, Clone)]
+pub struct PxTextFontFailed {
+    /// Path of the font that failed to load
+    pub path: AssetPath<'static>,
+    /// Why the font failed to load
+    pub error: String,
 }
 
 /// Pixel text that can be clicked and hovered on.
@@ -183,7 +901,7 @@ impl PickableText {
                     .collect();
                 let len = text.sections
                     .iter()
-                    .map(|s| s.value.len())
+                    .map(|s| s.value.chars().count())
                     .reduce(|x, y| x + y)
                     .unwrap_or_default();
 
@@ -232,3 +950,37 @@ pub enum EventType {
     RightClick,
     Hover,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typewriter_pauses_after_punctuation() {
+        let typewriter = PxTypewriter::new(20.)
+            .with_pause(".!?", 0.5)
+            .with_pause(",", 0.2);
+
+        assert_eq!(typewriter.delay('a'), 0.05);
+        assert_eq!(typewriter.delay('!'), 0.55);
+        assert_eq!(typewriter.delay(','), 0.25);
+    }
+
+    #[test]
+    fn stepped_gradients() {
+        let gradient = TextGradient::horizontal([Color::BLACK, Color::WHITE]).with_steps(3);
+        // Colors round trip through linear space, so they're only close
+        let assert_near = |t: f32, expected: Srgba| {
+            let sampled = gradient.sample(t).to_f32_array();
+            for (channel, expected) in sampled.into_iter().zip(expected.to_f32_array()) {
+                assert!((channel - expected).abs() < 1e-5, "{sampled:?} at {t}");
+            }
+        };
+
+        assert_near(0., Srgba::BLACK);
+        assert_near(0.3, Srgba::BLACK);
+        assert_near(0.5, Srgba::rgb(0.5, 0.5, 0.5));
+        assert_near(0.9, Srgba::WHITE);
+        assert_near(1., Srgba::WHITE);
+    }
+}
@@ -0,0 +1,93 @@
+use bevy::{prelude::*, render::primitives::Aabb};
+
+use crate::pxtext::{PxText3d, PxTextLayoutInfo};
+
+/// Sizes the quad for each rendered text, textured with the image the text was drawn on
+#[allow(clippy::type_complexity)]
+pub(crate) fn render_3d_system(
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    q_text: Query<(
+        Entity,
+        &PxText3d,
+        &Handle<Image>,
+        &PxTextLayoutInfo,
+        Option<&Handle<Mesh>>,
+        Option<&Handle<StandardMaterial>>,
+    ), Or<(Changed<PxTextLayoutInfo>, Changed<PxText3d>)>>,
+    mut commands: Commands,
+) {
+    for (entity, world, image, info, mesh_handle, material_handle) in &q_text {
+        let mesh = Mesh::from(Rectangle::from_size(quad_size(world, info)));
+
+        // The bounds are only calculated for meshes without any, so they're removed to be found again
+        match mesh_handle.and_then(|handle| meshes.get_mut(handle)) {
+            Some(old) => {
+                *old = mesh;
+                commands.entity(entity).remove::<Aabb>();
+            }
+            None => {
+                commands.entity(entity).insert(meshes.add(mesh));
+            }
+        }
+
+        match material_handle.and_then(|handle| materials.get_mut(handle)) {
+            Some(material) => if material.alpha_mode != world.alpha_mode {
+                material.alpha_mode = world.alpha_mode;
+            },
+            None => {
+                // The text image keeps its nearest sampler, and is lit the same from every side
+                let material = materials.add(StandardMaterial {
+                    base_color_texture: Some(image.clone()),
+                    alpha_mode: world.alpha_mode,
+                    unlit: true,
+                    cull_mode: None,
+                    double_sided: true,
+                    ..Default::default()
+                });
+                commands.entity(entity).insert(material);
+            }
+        }
+    }
+}
+
+/// Finds the size of the quad in world units, with each pixel of the image taking up the same space
+fn quad_size(world: &PxText3d, info: &PxTextLayoutInfo) -> Vec2 {
+    info.size.as_vec2() / world.pixels_per_unit
+}
+
+/// Turns billboarded text to face the active 3D camera.
+/// Only the rendered rotation changes, leaving the `Transform` as it was.
+pub(crate) fn billboard_system(
+    q_camera: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    mut q_text: Query<(&PxText3d, &mut GlobalTransform), Without<Camera3d>>,
+) {
+    let Some((_, camera_transform)) = q_camera
+        .iter()
+        .filter(|(camera, _)| camera.is_active)
+        .max_by_key(|(camera, _)| camera.order)
+    else {
+        return;
+    };
+    let (_, rotation, _) = camera_transform.to_scale_rotation_translation();
+
+    for (world, mut global_transform) in &mut q_text {
+        if world.billboard {
+            let (scale, _, translation) = global_transform.to_scale_rotation_translation();
+            *global_transform = Transform { translation, rotation, scale }.into();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quads_are_sized_in_pixels_per_unit() {
+        let world = PxText3d { pixels_per_unit: 16., ..default() };
+        let info = PxTextLayoutInfo { size: UVec2::new(40, 8), ..default() };
+
+        assert_eq!(quad_size(&world, &info), Vec2::new(2.5, 0.5));
+    }
+}
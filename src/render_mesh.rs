@@ -0,0 +1,164 @@
+use ahash::AHashMap;
+use bevy::{prelude::*, render::{mesh::{Indices, PrimitiveTopology}, primitives::Aabb, render_asset::RenderAssetUsages}, sprite::Mesh2dHandle};
+
+use crate::{layout::{LaidOutGlyph, TextLayout}, pxfont::PxFont, pxtext::{GradientDirection, PxText, PxTextMesh}, render_text::{background_rects, decoration_rects, effect_offset, LaidOutText, TextLayouts, Tints}};
+
+#[allow(clippy::type_complexity)]
+pub(crate) fn render_mesh_system(
+    mut layouts: TextLayouts,
+    mut font_changes: EventReader<AssetEvent<PxFont>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut atlas_materials: Local<AHashMap<AssetId<PxFont>, Handle<ColorMaterial>>>,
+    q_text: Query<(
+        LaidOutText,
+        Option<&Mesh2dHandle>,
+        Option<&Handle<ColorMaterial>>,
+        &Transform,
+        Option<&Children>,
+    ), With<PxTextMesh>>,
+    mut commands: Commands,
+) {
+    let loaded = layouts.changed_fonts();
+
+    // Let go of the materials of fonts that were removed or reloaded, which hold on to their atlases
+    for event in font_changes.read() {
+        if let AssetEvent::Removed { id } | AssetEvent::Modified { id } = event {
+            atlas_materials.remove(id);
+        }
+    }
+
+    for (text, mesh_handle, material_handle, transform, children) in &q_text {
+        let relayout = text.changed(&loaded);
+        let Some(draw) = layouts.layout(&mut commands, &text, &text.text, relayout, children.map(|children| (children, transform))) else {
+            continue;
+        };
+        let mesh = build_mesh(&draw.layout.text, draw.font, &draw.layout.layout, draw.time, draw.revealed);
+
+        // Update the glyph quads, reusing the mesh from last time.
+        // The bounds are only calculated for meshes without any, so they're removed to be found again.
+
+        match mesh_handle.and_then(|handle| meshes.get_mut(&handle.0)) {
+            Some(old) => {
+                *old = mesh;
+                commands.entity(text.entity).remove::<Aabb>();
+            }
+            None => {
+                commands.entity(text.entity).insert(Mesh2dHandle(meshes.add(mesh)));
+            }
+        }
+
+        // Text in the same font shares one material, so it can be drawn in a batch
+        let material = atlas_materials
+            .entry(draw.layout.text.font.id())
+            .or_insert_with(|| materials.add(ColorMaterial::from(draw.font.atlas.clone())));
+        if material_handle != Some(material) {
+            commands.entity(text.entity).insert(material.clone());
+        }
+
+        draw.store(&mut commands, text.entity);
+    }
+}
+
+/// Builds a quad for each background, glyph and decoration, centered like a sprite of the rendered image.
+/// Each quad's vertices carry the color of its section, or of its part of a gradient,
+/// and glyph quads are moved by their effect at `time`. Characters from index `revealed` on are left out.
+fn build_mesh(text: &PxText, font: &PxFont, layout: &TextLayout, time: f32, revealed: usize) -> Mesh {
+    let atlas_size = UVec2::new(font.source.width(), font.source.height() + 1).as_vec2();
+    let white = (UVec2::new(0, font.source.height()).as_vec2() + 0.5) / atlas_size;
+    let corner = Vec2::new(-(layout.image_size.x as f32), layout.image_size.y as f32) / 2.;
+    let to_local = |canvas: IVec2| corner + Vec2::new(canvas.x as f32, -(canvas.y as f32)) * layout.scale;
+
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    let mut colors = Vec::new();
+    let mut indices = Vec::new();
+    let mut push_quad = |rect: URect, offset: IVec2, uv: Rect, color: [f32; 4]| {
+        let (min, max) = (to_local(rect.min.as_ivec2() + offset), to_local(rect.max.as_ivec2() + offset));
+        let start = positions.len() as u32;
+
+        positions.extend([
+            [min.x, min.y, 0.],
+            [max.x, min.y, 0.],
+            [max.x, max.y, 0.],
+            [min.x, max.y, 0.],
+        ]);
+        uvs.extend([
+            [uv.min.x, uv.min.y],
+            [uv.max.x, uv.min.y],
+            [uv.max.x, uv.max.y],
+            [uv.min.x, uv.max.y],
+        ]);
+        colors.extend([color; 4]);
+        indices.extend([start, start + 2, start + 1, start, start + 3, start + 2]);
+    };
+
+    for (rect, color) in background_rects(text, layout, revealed) {
+        push_quad(rect, IVec2::ZERO, Rect::from_corners(white, white), color.to_linear().to_f32_array());
+    }
+
+    // Gradients split quads into strips of one color each, unless a color is given
+    let tints = Tints::new(text, layout, time);
+    let mut push_tinted = |rect: URect, offset: IVec2, uv: Rect, glyph: &LaidOutGlyph, color: Option<Color>| {
+        let linear = |color: Srgba| LinearRgba::from(color).to_f32_array();
+        if let Some(color) = color {
+            return push_quad(rect, offset, uv, linear(color.to_srgba()));
+        }
+        let axis = match tints.direction(glyph) {
+            Some(GradientDirection::Horizontal) => 0,
+            Some(GradientDirection::Vertical) => 1,
+            _ => return push_quad(rect, offset, uv, linear(tints.color(glyph, rect.min))),
+        };
+        let color_at = |p: u32| {
+            let mut pos = rect.min;
+            pos[axis] = p;
+            tints.color(glyph, pos)
+        };
+        let (start, end) = (rect.min[axis], rect.max[axis]);
+
+        let mut run_start = start;
+        let mut run_color = color_at(start);
+        for p in start + 1..=end {
+            let color = (p < end).then(|| color_at(p));
+            if color == Some(run_color) {
+                continue;
+            }
+
+            let (mut strip, mut strip_uv) = (rect, uv);
+            strip.min[axis] = run_start;
+            strip.max[axis] = p;
+            let uv_at = |p: u32| uv.min[axis] + (p - start) as f32 / (end - start) as f32 * uv.size()[axis];
+            strip_uv.min[axis] = uv_at(run_start);
+            strip_uv.max[axis] = uv_at(p);
+            push_quad(strip, offset, strip_uv, linear(run_color));
+
+            run_start = p;
+            run_color = color.unwrap_or(run_color);
+        }
+    };
+
+    let mut previous: Option<&LaidOutGlyph> = None;
+    for glyph in layout.glyphs.iter().filter(|glyph| glyph.index < revealed) {
+        if let Some(src_rect) = glyph.src_rect {
+            let uv = Rect::from_corners(
+                src_rect.min.as_vec2() / atlas_size,
+                (src_rect.max + UVec2::ONE).as_vec2() / atlas_size,
+            );
+            let rect = URect::from_corners(glyph.pos, glyph.pos + glyph.size);
+            push_tinted(rect, effect_offset(text, glyph, time), uv, glyph, None);
+        }
+
+        for (rect, color) in decoration_rects(text, font, layout, previous, glyph) {
+            push_tinted(rect, IVec2::ZERO, Rect::from_corners(white, white), glyph, color);
+        }
+
+        previous = Some(glyph);
+    }
+
+    // Kept in the main world, so the mesh can be updated in place
+    Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors)
+        .with_inserted_indices(Indices::U32(indices))
+}
@@ -1,336 +1,768 @@
-use bevy::{prelude::*, render::{render_asset::RenderAssetUsages, render_resource::{Extent3d, TextureDimension, TextureFormat}, texture::ImageSampler}};
-use image::{GenericImage, GenericImageView, Rgba, RgbaImage};
+use std::{borrow::Cow, f32::consts::TAU};
 
-use crate::{pxfont::PxFont, pxtext::{PickRect, PickableText, PxText, WrapMode}};
+use bevy::{asset::{AssetLoadFailedEvent, LoadState}, ecs::{query::QueryData, system::SystemParam}, prelude::*, render::{render_asset::RenderAssetUsages, render_resource::{Extent3d, TextureDimension, TextureFormat}, texture::ImageSampler}};
+use image::{imageops::{self, FilterType}, Rgba, RgbaImage};
 
+use crate::{layout::{fit_text, FittedText, FontLoad, LaidOutGlyph, StoredLayout, TextLayout}, pxfont::PxFont, pxtext::{DecorationStyle, FitMode, GradientDirection, PickRect, PickableText, PxText, PxTextFontFailed, PxTextLayoutInfo, PxTextPages, PxTextSection, PxTypewriter, TextEffect}};
+
+/// Text that is rendered to an image
+#[cfg(feature = "3d")]
+type ImageText = Or<(With<Sprite>, With<crate::pxtext::PxText3d>)>;
+#[cfg(not(feature = "3d"))]
+type ImageText = With<Sprite>;
+
+#[allow(clippy::type_complexity)]
 pub(crate) fn prepare_text_system(
     mut images: ResMut<Assets<Image>>,
-    q_text: Query<Entity, Without<Handle<Image>>>,
+    q_text: Query<Entity, (With<PxText>, ImageText, Without<Handle<Image>>)>,
     mut commands: Commands,
 ) {
     for entity in q_text.iter() {
-        let handle = images.add(Image::default());
+        let handle = images.add(text_image(UVec2::ONE, vec![0; 4]));
         commands.entity(entity).insert(handle);
     }
 }
 
+/// Frees the image of an entity that no longer has text.
+/// Despawned entities free theirs when the handle drops.
+pub(crate) fn cleanup_text_system(
+    mut images: ResMut<Assets<Image>>,
+    mut removed: RemovedComponents<PxText>,
+    q_image: Query<&Handle<Image>, ImageText>,
+    mut commands: Commands,
+) {
+    for entity in removed.read() {
+        if let Ok(handle) = q_image.get(entity) {
+            images.remove(handle);
+            commands.entity(entity).remove::<Handle<Image>>();
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
 pub(crate) fn render_text_system(
-    fonts: Res<Assets<PxFont>>,
+    mut layouts: TextLayouts,
     mut images: ResMut<Assets<Image>>,
-    q_text: Query<(&PxText, &Handle<Image>, &Transform, Option<&Children>), Changed<PxText>>,
-    q_pickable: Query<&PickableText>,
+    q_text: Query<(LaidOutText, &Handle<Image>, &Transform, Option<&Children>), ImageText>,
     mut commands: Commands,
 ) {
-    for (
-        text,
-        handle,
-        transform,
-        children
-    ) in &q_text {
-        let font = fonts.get(&text.font).unwrap();
-        let width = text_width(text, font);
-        let height = text_height(text, font);
-
-        let mut output = RgbaImage::new(width, height);
-        let mut x = 0;
-        let mut y = 0;
-        
-        let mut first_after_space = true;
-        let mut last_char = None;
-        let mut first_in_section;
-
-        'draw_glyphs: for section in &text.sections {
-            first_in_section = true;
-            let string = if WrapMode::WrapWord == text.wrap_mode {
-                if let Some(bounds) = text.bounding_box {
-                    &wrap_words(
-                        (x, y),
-                        &section.value,
-                        font,
-                        text.line_spacing,
-                        bounds,
-                    )
-                } else {
-                    &section.value
+    let loaded = layouts.changed_fonts();
+
+    for (text, handle, transform, children) in &q_text {
+        let relayout = text.changed(&loaded);
+        let Some(draw) = layouts.layout(&mut commands, &text, &text.text, relayout, children.map(|children| (children, transform))) else {
+            continue;
+        };
+        let output = draw.draw();
+
+        // Render text, only resizing the image when its size changes
+
+        match images.get_mut(handle) {
+            Some(image) => {
+                let size = Extent3d {
+                    width: output.width(),
+                    height: output.height(),
+                    depth_or_array_layers: 1,
+                };
+                if image.texture_descriptor.size != size {
+                    image.resize(size);
                 }
-            } else {
-                &section.value
-            };
+                image.data = output.into_vec();
+            }
+            None => {
+                images.insert(handle, text_image(UVec2::new(output.width(), output.height()), output.into_vec()));
+            }
+        }
 
-            for c in string.chars() {
-                if c == '\n' {
-                    x = 0;
-                    y += font.ascender + font.descender + text.line_spacing;
-                    first_after_space = true;
-                } else if let Some(glyph) = font.char_map.get(&c) {
-                    if first_in_section && last_char == Some(' ') {
-                        first_after_space = true;
-                    }
+        draw.store(&mut commands, text.entity);
+    }
+}
 
-                    if x + glyph.src_rect.width() + 1 > output.width() {
-                        x = 0;
-                        y += font.ascender + font.descender + text.line_spacing;
-                        first_after_space = true;
-                    }
+/// The parts of a text entity that decide when it's laid out and drawn again
+#[derive(QueryData)]
+pub(crate) struct LaidOutText {
+    pub(crate) entity: Entity,
+    pub(crate) text: Ref<'static, PxText>,
+    stored: Option<&'static StoredLayout>,
+    typewriter: Option<Ref<'static, PxTypewriter>>,
+    pages: Option<Ref<'static, PxTextPages>>,
+}
 
-                    if y + glyph.src_rect.height() + 1 > output.height() {
-                        break 'draw_glyphs;
-                    }
+impl LaidOutTextItem<'_> {
+    /// Whether the text, its page or one of the fonts in `loaded` changed, which lays it out again
+    pub(crate) fn changed(&self, loaded: &[AssetId<PxFont>]) -> bool {
+        self.text.is_changed()
+            || self.pages.as_ref().is_some_and(|pages| pages.is_changed())
+            || loaded.iter().any(|id| uses_font(&self.text, *id))
+    }
+}
 
-                    if let Err(e) = output.copy_from(
-                        &*font.source.view(
-                            glyph.src_rect.min.x,
-                            glyph.src_rect.min.y,
-                            glyph.src_rect.width() + 1,
-                            glyph.src_rect.height() + 1,
-                        ), x, y) {
-                        error!("Image error: {e}");
-                    }
+/// Lays out text for the render systems, which draw it from a fresh layout when it changed,
+/// and again from its last layout when more of it is revealed or it's animated
+#[derive(SystemParam)]
+pub(crate) struct TextLayouts<'w, 's> {
+    fonts: Res<'w, Assets<PxFont>>,
+    asset_server: Res<'w, AssetServer>,
+    font_events: FontEvents<'w, 's>,
+    q_pickable: Query<'w, 's, &'static PickableText>,
+    time: Res<'w, Time>,
+}
 
-                    let x_min = if first_after_space {
-                        x
-                    } else {
-                        x - 1
-                    };
-
-                    if section.underline {
-                        let j = if font.descender < 2 {
-                            y + font.ascender
-                        } else {
-                            y + font.ascender + 1
-                        };
-
-                        for i in x_min..=x + glyph.src_rect.width() {
-                            output[(i, j)] = Rgba::from([255, 255, 255, 255]);
-                        }
+impl TextLayouts<'_, '_> {
+    /// Finds the fonts that finished loading, changed or failed since the last run
+    pub(crate) fn changed_fonts(&mut self) -> Vec<AssetId<PxFont>> {
+        self.font_events.read()
+    }
+
+    /// Lays out `bounded`, the entity's text as it fits where it's drawn, when `relayout` is set.
+    /// A fresh layout updates the `PxTextLayoutInfo`, and the pick rects of the `PickableText`
+    /// children when `picking` is given. Returns `None` if there's nothing new to draw,
+    /// or the fonts are still loading, marking the text if one of them failed.
+    pub(crate) fn layout<'a>(
+        &'a self,
+        commands: &mut Commands,
+        text: &'a LaidOutTextItem,
+        bounded: &PxText,
+        relayout: bool,
+        picking: Option<(&Children, &Transform)>,
+    ) -> Option<TextToDraw<'a>> {
+        let revealing = text.typewriter.as_ref().is_some_and(|typewriter| typewriter.is_changed());
+        if !relayout && !revealing && !text.stored.is_some_and(StoredLayout::animated) {
+            return None;
+        }
+
+        let layout = match relayout {
+            true => {
+                // Wait for the font to load, which lays the text out again, unless it failed
+                let page = text.pages.as_ref().map(|pages| pages.page);
+                let Some(fitted) = fit_text(bounded, page, |handle| font_load(&self.fonts, &self.asset_server, handle)) else {
+                    if let Some(failed) = font_failure(&text.text, &self.asset_server) {
+                        commands.entity(text.entity).insert(failed);
                     }
+                    return None;
+                };
 
-                    let rgba = section.color.to_srgba();
-                    for i in x_min..=x + glyph.src_rect.width() {
-                        for j in y..=y + glyph.src_rect.height() {
-                            let px = output[(i, j)];
-                            output[(i, j)] = Rgba::from([
-                                (rgba.red * px[0] as f32) as u8,
-                                (rgba.green * px[1] as f32) as u8,
-                                (rgba.blue * px[2] as f32) as u8,
-                                (rgba.alpha * px[3] as f32) as u8,
-                            ]);
+                // Draw pick rects
+
+                if let Some((children, transform)) = picking {
+                    for child in children.iter() {
+                        if let Ok(pickable) = self.q_pickable.get(*child) {
+                            let (_string, range) = pickable.get_string(&text.text);
+                            let rects = pick_rects(&fitted.layout, &range, transform);
+
+                            commands.entity(*child).insert(PickRect(rects));
                         }
                     }
+                }
 
-                    x += glyph.src_rect.width() + 1 + font.spacing;
+                commands.entity(text.entity).insert(layout_info(&fitted)).remove::<PxTextFontFailed>();
+                Cow::Owned(StoredLayout::new(fitted))
+            }
+            false => Cow::Borrowed(text.stored?),
+        };
+
+        Some(TextToDraw {
+            font: self.fonts.get(&layout.text.font)?,
+            layout,
+            revealed: text.typewriter.as_ref().map_or(usize::MAX, |typewriter| typewriter.revealed),
+            time: self.time.elapsed_seconds(),
+        })
+    }
+}
 
-                    if first_after_space {
-                        first_after_space = false;
-                    }
+/// Text to draw this frame, with the font it was fitted with
+pub(crate) struct TextToDraw<'a> {
+    pub(crate) font: &'a PxFont,
+    /// A fresh layout, or the last one when the text is only drawn again
+    pub(crate) layout: Cow<'a, StoredLayout>,
+    /// Index of the first character the typewriter hasn't revealed
+    pub(crate) revealed: usize,
+    /// Seconds since startup, which moves the text's effects
+    pub(crate) time: f32,
+}
 
-                    last_char = Some(c);
-                }
+impl TextToDraw<'_> {
+    /// Draws the text as it looks now
+    pub(crate) fn draw(&self) -> RgbaImage {
+        draw_text(&self.layout.text, self.font, &self.layout.layout, self.time, self.revealed)
+    }
 
-                if first_in_section {
-                    first_in_section = false;
-                }
+    /// Keeps a fresh layout on the entity, to draw the text again from
+    pub(crate) fn store(self, commands: &mut Commands, entity: Entity) {
+        if let Cow::Owned(layout) = self.layout {
+            commands.entity(entity).insert(layout);
+        }
+    }
+}
+
+/// Describes a layout for `PxTextLayoutInfo`
+fn layout_info(fitted: &FittedText) -> PxTextLayoutInfo {
+    PxTextLayoutInfo {
+        size: image_size(&fitted.text, &fitted.layout),
+        content_size: fitted.layout.content,
+        line_count: fitted.layout.lines.len() as u32,
+        page_count: fitted.layout.pages as u32,
+        page: fitted.layout.page.0 as u32,
+        page_chars: fitted.layout.page.1.clone(),
+        overflowed: fitted.layout.overflowed,
+        scale: fitted.scale,
+        font: fitted.text.font.clone(),
+    }
+}
+
+/// Events for fonts that finished loading, changed or failed to load,
+/// any of which can change the font that text is laid out with
+#[derive(SystemParam)]
+pub(crate) struct FontEvents<'w, 's> {
+    changed: EventReader<'w, 's, AssetEvent<PxFont>>,
+    failed: EventReader<'w, 's, AssetLoadFailedEvent<PxFont>>,
+}
+
+impl FontEvents<'_, '_> {
+    /// Finds the fonts that finished loading, changed or failed since the last run
+    pub(crate) fn read(&mut self) -> Vec<AssetId<PxFont>> {
+        let changed = self.changed
+            .read()
+            .filter_map(|event| match event {
+                AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => Some(*id),
+                _ => None,
+            });
+        let failed = self.failed.read().map(|failure| failure.id);
+        changed.chain(failed).collect()
+    }
+}
+
+/// Finds whether a font can be laid out with, or is still loading
+pub(crate) fn font_load<'a>(fonts: &'a Assets<PxFont>, asset_server: &AssetServer, handle: &Handle<PxFont>) -> FontLoad<'a> {
+    match fonts.get(handle) {
+        Some(font) => FontLoad::Loaded(font),
+        None => match asset_server.load_state(handle) {
+            LoadState::Failed(_) => FontLoad::Failed,
+            _ => FontLoad::Loading,
+        },
+    }
+}
+
+/// Whether the text can be laid out with the font, including fonts it may fall back to
+pub(crate) fn uses_font(text: &PxText, id: AssetId<PxFont>) -> bool {
+    text_fonts(text).any(|font| font.id() == id)
+}
+
+/// The text's font, followed by the fonts it may fall back to
+pub(crate) fn text_fonts(text: &PxText) -> impl Iterator<Item = &Handle<PxFont>> {
+    let fallbacks = match &text.fit {
+        FitMode::Fonts(fonts) => fonts.as_slice(),
+        _ => &[],
+    };
+    std::iter::once(&text.font).chain(fallbacks)
+}
+
+/// Marks text whose font failed to load, which is never rendered
+pub(crate) fn font_load_failed_system(
+    mut failures: EventReader<AssetLoadFailedEvent<PxFont>>,
+    q_text: Query<(Entity, &PxText)>,
+    mut commands: Commands,
+) {
+    for failure in failures.read() {
+        error!("Failed to load the font {}: {}", failure.path, failure.error);
+
+        for (entity, text) in &q_text {
+            if uses_font(text, failure.id) {
+                commands.entity(entity).insert(PxTextFontFailed {
+                    path: failure.path.clone(),
+                    error: failure.error.to_string(),
+                });
             }
         }
+    }
+}
+
+/// Describes the first of the text's fonts that failed to load, for text that
+/// can't be laid out. Text spawned after its font failed never sees the failure event.
+fn font_failure(text: &PxText, asset_server: &AssetServer) -> Option<PxTextFontFailed> {
+    text_fonts(text).find_map(|font| {
+        match asset_server.load_state(font) {
+            LoadState::Failed(error) => Some(PxTextFontFailed {
+                path: asset_server.get_path(font)?.into_owned(),
+                error: error.to_string(),
+            }),
+            _ => None,
+        }
+    })
+}
 
-        // Render text
-
-        let mut image = Image::new(
-            Extent3d {
-                width,
-                height,
-                depth_or_array_layers: 1,
-            },
-            TextureDimension::D2,
-            output.into_vec(),
-            TextureFormat::Rgba8UnormSrgb,
-            RenderAssetUsages::RENDER_WORLD
-        );
-        image.sampler = ImageSampler::nearest();
-
-        let corner = IVec2::new(
-            transform.translation.as_ivec3().x - image.size().as_ivec2().x / 2,
-            transform.translation.as_ivec3().y + image.size().as_ivec2().y / 2,
-        );
-
-        // Draw pick rects
-
-        if let Some(children) = children {
-            for child in children.iter() {
-                if let Ok(pickable) = q_pickable.get(*child) {
-                    let font = fonts.get(&text.font).unwrap();
-                    let mut x = corner.x;
-                    let mut y = corner.y;
-                    let mut idx = 0;
-                    let mut max: Option<IVec2> = None;
-                    let mut min: Option<IVec2> = None;
-
-                    let mut rects = Vec::new();
-
-                    let (_string, range) = pickable.get_string(text);
-
-                    'make_rect: for section in &text.sections {
-                        for c in section.value.chars() {
-                            if c == '\n' {
-                                if min.is_none() {
-                                    min = Some(IVec2::new(
-                                        x,
-                                        y - (font.ascender + font.descender) as i32
-                                    ));
-                                }
-
-                                if let Some(max_rect) = max {
-                                    if let Some(min_rect) = min {
-                                        rects.push(IRect::from_corners(
-                                            (max_rect.as_vec2() * transform.scale.truncate()).as_ivec2(),
-                                            (min_rect.as_vec2() * transform.scale.truncate()).as_ivec2(),
-                                        ));
-                                    }
-                                }
-
-                                x = corner.x;
-                                y -= (font.ascender + font.descender + text.line_spacing) as i32;
-
-                                max = Some(IVec2::new(x, y));
-                                min = None;
-                            } else if let Some(glyph) = font.char_map.get(&c) {
-                                x += (glyph.src_rect.width() + 1 + font.spacing) as i32;
-
-                                if let Some(_) = max {
-                                    if idx == range.end - 1 {
-                                        min = Some(IVec2::new(
-                                            x,
-                                            y - (font.ascender + font.descender) as i32
-                                        ));
-                                        break 'make_rect;
-                                    }
-                                } else {
-                                    if idx == range.start {
-                                        max = Some(IVec2::new(x, y));
-                                    }
-                                }
-                                idx += 1;
-                            }
-                        }
-                    }
+/// Makes an image for text to be drawn on, kept in the main world so it can be redrawn
+pub(crate) fn text_image(size: UVec2, data: Vec<u8>) -> Image {
+    let mut image = Image::new(
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image.sampler = ImageSampler::nearest();
+    image
+}
 
-                    if let Some(max_rect) = max {
-                        if let Some(min_rect) = min {
-                            rects.push(IRect::from_corners(
-                                (max_rect.as_vec2() * transform.scale.truncate()).as_ivec2(),
-                                (min_rect.as_vec2() * transform.scale.truncate()).as_ivec2(),
-                            ));
-                        }
+/// Draws the text as it looks `time` seconds in, which only matters for sections with effects,
+/// leaving out the characters from index `revealed` on
+pub(crate) fn draw_text(
+    text: &PxText,
+    font: &PxFont,
+    layout: &TextLayout,
+    time: f32,
+    revealed: usize,
+) -> RgbaImage {
+    // Effects can move glyphs past the edges of the canvas, into a margin around it
+    let margin = effect_margin(text);
+    let padded = layout.size + 2 * margin;
+    let mut output = RgbaImage::new(padded.x, padded.y);
+    let mut previous: Option<&LaidOutGlyph> = None;
+    let tints = Tints::new(text, layout, time);
+
+    for (rect, color) in background_rects(text, layout, revealed) {
+        let rgba = color.to_srgba().to_u8_array();
+        for y in rect.min.y..rect.max.y {
+            for x in rect.min.x..rect.max.x {
+                output[(x + margin, y + margin)] = Rgba::from(rgba);
+            }
+        }
+    }
+
+    for glyph in layout.glyphs.iter().filter(|glyph| glyph.index < revealed) {
+        let size = glyph.size;
+        let tint = |px: Rgba<u8>, pos: UVec2, color: Option<Color>| {
+            let rgba = color.map_or_else(|| tints.color(glyph, pos), |color| color.to_srgba());
+            // Round, since colors that went through linear space can fall just short of 1
+            Rgba::from([
+                (rgba.red * px[0] as f32).round() as u8,
+                (rgba.green * px[1] as f32).round() as u8,
+                (rgba.blue * px[2] as f32).round() as u8,
+                (rgba.alpha * px[3] as f32).round() as u8,
+            ])
+        };
+
+        if let Some(src_rect) = glyph.src_rect {
+            // Effects move the pixels, but color them as if they hadn't moved
+            let offset = effect_offset(text, glyph, time);
+            for j in 0..size.y {
+                for i in 0..size.x {
+                    let pos = glyph.pos + UVec2::new(i, j);
+                    let (x, y) = (pos + margin).saturating_add_signed(offset).into();
+                    let px = *font.source.get_pixel(
+                        src_rect.min.x + i / glyph.scale,
+                        src_rect.min.y + j / glyph.scale,
+                    );
+
+                    if x < output.width() && y < output.height() && px[3] != 0 {
+                        output[(x, y)] = tint(px, pos, None);
                     }
+                }
+            }
+        }
 
-                    commands.entity(*child).insert(PickRect(rects));
+        for (rect, color) in decoration_rects(text, font, layout, previous, glyph) {
+            for y in rect.min.y..rect.max.y.min(layout.size.y) {
+                for x in rect.min.x..rect.max.x.min(layout.size.x) {
+                    let white = Rgba::from([255, 255, 255, 255]);
+                    output[(x + margin, y + margin)] = tint(white, UVec2::new(x, y), color);
                 }
             }
         }
 
-        *images.get_mut(handle).unwrap() = image;
+        previous = Some(glyph);
     }
-}
 
-fn text_width(text: &PxText, font: &PxFont) -> u32 {
-    if let Some(bounds) = text.bounding_box {
-        return bounds.x;
+    if layout.image_size != layout.size {
+        let shrunk = (padded.as_vec2() * layout.scale).as_uvec2().max(UVec2::ONE);
+        let shrunk = imageops::resize(&output, shrunk.x, shrunk.y, FilterType::Nearest);
+        let size = image_size(text, layout);
+        output = RgbaImage::new(size.x, size.y);
+        imageops::overlay(&mut output, &shrunk, 0, 0);
     }
 
-    let mut width = 0;
-    let mut line_width = 0;
-    let mut first_char = true;
+    output
+}
 
-    for section in &text.sections {
-        for c in section.value.chars() {
-            if first_char {
-                first_char = false;
-            } else {
-                line_width += font.spacing;
+/// Finds how far effects can move glyphs out of the canvas, in canvas pixels
+pub(crate) fn effect_margin(text: &PxText) -> u32 {
+    text.sections
+        .iter()
+        .map(|section| match section.effect {
+            Some(TextEffect::Wave { amplitude, .. } | TextEffect::Shake { amplitude }) => {
+                amplitude.abs().round() as u32 * section.scale.max(1)
             }
+            _ => 0,
+        })
+        .max()
+        .unwrap_or_default()
+}
 
-            if c == '\n' {
-                if line_width > width {
-                    width = line_width;
-                }
-                line_width = 0;
-                first_char = true;
-            } else if let Some(glyph) = font.char_map.get(&c) {
-                line_width += glyph.src_rect.width() + 1;
-            } else {
-                error!("The font {} does not contain the character {c}", font.name);
-            }
+/// Finds the size of the image the text is drawn on, with room around it for effects.
+/// The text stays centered in it.
+pub(crate) fn image_size(text: &PxText, layout: &TextLayout) -> UVec2 {
+    let margin = (effect_margin(text) as f32 * layout.scale).round() as u32;
+    layout.image_size + 2 * margin
+}
+
+/// Finds the color of each pixel of text, following the gradients of its sections
+pub(crate) struct Tints<'a> {
+    text: &'a PxText,
+    /// Area each section's glyphs cover
+    spans: Vec<Option<URect>>,
+    /// Index of the first character of each section
+    starts: Vec<usize>,
+    /// Area each line runs through, which vertical gradients span
+    lines: Vec<URect>,
+    /// Seconds into the effects
+    time: f32,
+}
+
+impl<'a> Tints<'a> {
+    pub(crate) fn new(text: &'a PxText, layout: &TextLayout, time: f32) -> Self {
+        let mut spans = vec![None; text.sections.len()];
+        for glyph in &layout.glyphs {
+            let rect = URect::from_corners(glyph.pos, glyph.pos + glyph.size);
+            let span: &mut Option<URect> = &mut spans[glyph.section];
+            *span = Some(span.map_or(rect, |span| span.union(rect)));
         }
+
+        let starts = text.sections
+            .iter()
+            .scan(0, |start, section| {
+                let first = *start;
+                *start += section.value.chars().count();
+                Some(first)
+            })
+            .collect();
+
+        let lines = layout.lines.iter().map(|line| line.rect).collect();
+
+        Self { text, spans, starts, lines, time }
     }
-    
-    if line_width > width {
-        width = line_width;
+
+    /// Which way the color of a glyph changes, if it changes at all
+    pub(crate) fn direction(&self, glyph: &LaidOutGlyph) -> Option<GradientDirection> {
+        self.text.sections[glyph.section]
+            .gradient
+            .as_ref()
+            .map(|gradient| gradient.direction)
+            .filter(|direction| *direction != GradientDirection::PerChar)
     }
 
-    width + 1
-}
+    /// Finds the color of the glyph at `pos` in the canvas
+    pub(crate) fn color(&self, glyph: &LaidOutGlyph, pos: UVec2) -> Srgba {
+        let section = &self.text.sections[glyph.section];
+        let color = self.base_color(glyph, pos);
+        let cycle = |speed: f32| self.time * speed * TAU;
+
+        match section.effect {
+            Some(TextEffect::Rainbow { speed }) => {
+                let hue = (cycle(speed).to_degrees() + glyph.index as f32 * 24.).rem_euclid(360.);
+                Hsla::new(hue, 1., 0.5, color.alpha).into()
+            }
+            Some(TextEffect::Pulse { speed }) => {
+                color.with_alpha(color.alpha * (0.75 + 0.25 * cycle(speed).cos()))
+            }
+            _ => color,
+        }
+    }
+
+    /// Finds the color of the glyph at `pos` before any effect
+    fn base_color(&self, glyph: &LaidOutGlyph, pos: UVec2) -> Srgba {
+        let section = &self.text.sections[glyph.section];
+        let Some(gradient) = &section.gradient else {
+            return section.color.to_srgba();
+        };
+
+        // Color whole pixels of the font, even when they're scaled up
+        let scale = IVec2::splat(glyph.scale as i32);
+        let offset = (pos.as_ivec2() - glyph.pos.as_ivec2()).div_euclid(scale) * scale;
+        let pos = (glyph.pos.as_ivec2() + offset).max(IVec2::ZERO).as_uvec2();
+
+        let fraction = |offset: u32, length: u32| match length {
+            0 | 1 => 0.,
+            _ => offset as f32 / (length - 1) as f32,
+        };
+        let t = match gradient.direction {
+            GradientDirection::Horizontal => {
+                let span = self.spans[glyph.section].unwrap_or_default();
+                fraction(pos.x.saturating_sub(span.min.x), span.width())
+            }
+            GradientDirection::Vertical => {
+                let line = self.lines[glyph.line];
+                fraction(pos.y.saturating_sub(line.min.y), line.height())
+            }
+            GradientDirection::PerChar => fraction(
+                (glyph.index - self.starts[glyph.section]) as u32,
+                section.value.chars().count() as u32,
+            ),
+        };
 
-fn text_height(text: &PxText, font: &PxFont) -> u32 {
-    if let Some(bounds) = text.bounding_box {
-        return bounds.y;
+        gradient.sample(t)
     }
+}
+
+/// Finds how far the effect of a glyph's section moves it `time` seconds in, in whole font pixels
+pub(crate) fn effect_offset(text: &PxText, glyph: &LaidOutGlyph, time: f32) -> IVec2 {
+    let index = glyph.index as u32;
+    let offset = match text.sections[glyph.section].effect {
+        Some(TextEffect::Wave { amplitude, speed }) => {
+            // Each glyph rises a little after the one before it
+            let phase = (time * speed - index as f32 * 0.1) * TAU;
+            Vec2::new(0., -phase.sin() * amplitude)
+        }
+        Some(TextEffect::Shake { amplitude }) => {
+            // Jump somewhere new 15 times a second, differently for each glyph
+            let step = (time * 15.).floor() as u32;
+            let random = |salt: u32| {
+                let mut hash = index.wrapping_mul(0x9e37_79b9) ^ step.wrapping_mul(0x85eb_ca6b) ^ salt;
+                hash ^= hash >> 15;
+                hash = hash.wrapping_mul(0x2c1b_3c6d);
+                hash ^= hash >> 12;
+                hash as f32 / u32::MAX as f32 * 2. - 1.
+            };
+            Vec2::new(random(1), random(2)) * amplitude
+        }
+        _ => Vec2::ZERO,
+    };
 
-    (font.ascender + font.descender + text.line_spacing) * (
-        text.sections.iter().map(|section|
-            section.value.chars().filter(|c| *c == '\n').count() as u32
-        ).sum::<u32>() + 1
-    )
+    offset.round().as_ivec2() * glyph.scale as i32
 }
 
-fn wrap_words(
-    start: (u32, u32),
-    text: &str,
+/// Finds the pixels of the underline, strikethrough and overline of a glyph,
+/// along with the color each is drawn in if it isn't the section's
+pub(crate) fn decoration_rects(
+    text: &PxText,
     font: &PxFont,
-    line_spacing: u32,
-    bounds: UVec2,
-) -> String {
-    let (mut x, mut y) = start;
-    let mut wrapped = String::with_capacity(text.len());
-
-    for word in text.split_inclusive(word_separator) {
-        let mut word_width = 0;
-        let mut first_char = true;
-        for c in word.chars() {
-            if first_char {
-                first_char = false;
-            } else {
-                word_width += font.spacing;
+    layout: &TextLayout,
+    previous: Option<&LaidOutGlyph>,
+    glyph: &LaidOutGlyph,
+) -> Vec<(URect, Option<Color>)> {
+    let section = &text.sections[glyph.section];
+    let underline_row = match font.descender < 2 {
+        true => font.ascender,
+        false => font.ascender + 1,
+    };
+    let decorations = |section: &PxTextSection| [
+        section.underline,
+        section.strikethrough,
+        section.overline,
+    ];
+    let rows = [underline_row, font.ascender.div_ceil(2), 0];
+
+    let mut rects: Vec<(URect, Option<Color>)> = Vec::new();
+    for (kind, decoration) in decorations(section).into_iter().enumerate() {
+        let Some(decoration) = decoration else {
+            continue;
+        };
+        let row = rows[kind];
+
+        // Continue the line across the gap from a neighbor with the same decoration
+        let x_min = match previous {
+            Some(prev) if prev.line == glyph.line && !layout.vertical
+                && decorations(&text.sections[prev.section])[kind].is_some() => {
+                prev.pos.x + prev.size.x
             }
+            _ => glyph.pos.x,
+        };
+        let x_max = glyph.pos.x + glyph.size.x;
+
+        let scale = glyph.scale;
+        let y_min = (glyph.pos.y + row * scale)
+            .saturating_add_signed(decoration.offset * scale as i32);
+        let thickness = decoration.thickness * scale;
+
+        // Patterns follow the font's pixels across the whole line, so they don't restart at each glyph
+        let mut x = x_min;
+        while x < x_max {
+            let column = x / scale;
+            let width = (scale - x % scale).min(x_max - x);
+            let y = match decoration.style {
+                DecorationStyle::Solid => Some(y_min),
+                DecorationStyle::Dotted => (column % 2 == 0).then_some(y_min),
+                DecorationStyle::Dashed => (column % 5 < 3).then_some(y_min),
+                DecorationStyle::Wavy => Some(y_min + [0, 1, 1, 0][column as usize % 4] * scale),
+            };
 
-            if let Some(glyph) = font.char_map.get(&c) {
-                word_width += glyph.src_rect.width() + 1;
+            if let Some(y) = y {
+                let rect = URect::new(x, y, x + width, y + thickness);
+                match rects.last_mut() {
+                    // Join with the last piece when it lines up
+                    Some((last, color)) if last.max.x == x && last.min.y == y
+                        && last.max.y == rect.max.y && *color == decoration.color => {
+                        last.max.x = rect.max.x;
+                    }
+                    _ => rects.push((rect, decoration.color)),
+                }
             }
-
+            x += width;
         }
+    }
 
-        if x + word_width + 1 > bounds.x {
-            wrapped += "\n";
-            x = word_width;
-            y += font.ascender + font.descender + line_spacing;
-        } else {
-            x += word_width + 1 + font.spacing;
-        }
+    rects
+}
+
+/// Builds a world space rect around the picked characters on each line
+pub(crate) fn pick_rects(
+    layout: &TextLayout,
+    range: &std::ops::Range<usize>,
+    transform: &Transform,
+) -> Vec<IRect> {
+    let scale = transform.scale.truncate();
+    let corner = transform.translation.truncate()
+        + Vec2::new(-(layout.image_size.x as f32), layout.image_size.y as f32) / 2. * scale;
+    let scale = scale * layout.scale;
+    let to_world = |local: UVec2| (
+        corner + Vec2::new(local.x as f32, -(local.y as f32)) * scale
+    ).as_ivec2();
+
+    layout
+        .runs(|glyph| range.contains(&glyph.index))
+        .into_iter()
+        .map(|run| IRect::from_corners(to_world(run.min), to_world(run.max)))
+        .collect()
+}
+
+/// Finds the boxes behind the revealed characters of each section with a background,
+/// padded and kept within the canvas
+pub(crate) fn background_rects(text: &PxText, layout: &TextLayout, revealed: usize) -> Vec<(URect, Color)> {
+    let canvas = URect::from_corners(UVec2::ZERO, layout.size);
+
+    text.sections
+        .iter()
+        .enumerate()
+        .filter_map(|(index, section)| section.background.map(|color| (index, section, color)))
+        .flat_map(|(index, section, color)| {
+            let padding = UVec2::splat(section.background_padding);
+            layout
+                .runs(|glyph| glyph.section == index && glyph.index < revealed)
+                .into_iter()
+                .map(move |run| {
+                    let padded = URect::from_corners(
+                        run.min.saturating_sub(padding),
+                        run.max + padding,
+                    );
+                    (padded.intersect(canvas), color)
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{layout::layout_text, pxtext::{DecorationStyle, PxTextSection, TextDecoration, TextGradient}, test_support::test_font};
+
+    #[test]
+    fn zero_scale_draws_like_one() {
+        let font = test_font();
+        let draw = |scale: u32| {
+            let mut section = PxTextSection::new("ab").with_underline(TextDecoration::default());
+            section.scale = scale;
+            let text = PxText::from_sections(vec![section], Handle::default());
+            let layout = layout_text(&text, &font, None);
+            draw_text(&text, &font, &layout, 0., usize::MAX)
+        };
+
+        assert_eq!(draw(0), draw(1));
+    }
 
-        if word.chars().last() == Some('\n') {
-            x = 0;
-            y += font.ascender + font.descender + line_spacing;
+    #[test]
+    fn vertical_gradients_span_the_line() {
+        let font = test_font();
+        let gradient = TextGradient::vertical([Color::BLACK, Color::WHITE]);
+        let text = PxText::from_sections(vec![
+            PxTextSection::new("a").with_gradient(gradient.clone()),
+            PxTextSection::new("b").with_gradient(gradient).with_scale(2),
+        ], Handle::default());
+        let layout = layout_text(&text, &font, None);
+        let tints = Tints::new(&text, &layout, 0.);
+        let (small, large) = (&layout.glyphs[0], &layout.glyphs[1]);
+
+        // Both glyphs have the color of the row of the line they're on
+        assert_eq!(tints.color(large, large.pos), Srgba::BLACK);
+        for y in (small.pos.y..small.pos.y + small.size.y).filter(|y| (y - large.pos.y) % 2 == 0) {
+            assert_eq!(tints.color(small, UVec2::new(small.pos.x, y)), tints.color(large, UVec2::new(large.pos.x, y)));
         }
+        assert_ne!(tints.color(small, small.pos), Srgba::BLACK);
+    }
 
-        if y + font.ascender + font.descender + 1 > bounds.y {
-            return wrapped;
+    #[test]
+    fn decorations_continue_across_spaces() {
+        let font = test_font();
+        let dashed = TextDecoration::default().with_style(DecorationStyle::Dashed);
+        let text = PxText::from_sections(vec![
+            PxTextSection::new("ab cd").with_underline(dashed),
+        ], Handle::default());
+        let layout = layout_text(&text, &font, None);
+
+        let mut columns = Vec::new();
+        let mut previous = None;
+        for glyph in &layout.glyphs {
+            for (rect, _) in decoration_rects(&text, &font, &layout, previous, glyph) {
+                assert_eq!((rect.min.y, rect.max.y), (3, 4));
+                columns.extend(rect.min.x..rect.max.x);
+            }
+            previous = Some(glyph);
         }
 
-        wrapped += word;
+        assert_eq!(columns, [0, 1, 2, 5, 6, 7, 10, 11, 12, 15, 16, 17]);
     }
 
-    wrapped
-}
+    #[test]
+    fn backgrounds_follow_wrapped_lines() {
+        let font = test_font();
+        let text = PxText::from_sections(vec![
+            PxTextSection::new("ab "),
+            PxTextSection::new("cd ef").with_background(Color::BLACK, 1),
+        ], Handle::default()).with_bounding_box(UVec2::new(18, 20));
+        let layout = layout_text(&text, &font, None);
+
+        let rects: Vec<URect> = background_rects(&text, &layout, usize::MAX)
+            .into_iter()
+            .map(|(rect, _)| rect)
+            .collect();
+        assert_eq!(rects, [URect::new(10, 0, 18, 5), URect::new(0, 4, 8, 10)]);
+    }
 
-fn word_separator(c: char) -> bool {
-    c.is_whitespace() || c == '-'
+    #[test]
+    fn waves_move_glyphs_in_turn() {
+        let font = test_font();
+        let wave = TextEffect::Wave { amplitude: 2., speed: 1. };
+        let text = PxText::from_sections(vec![
+            PxTextSection::new("a").with_scale(2),
+            PxTextSection::new("bcdef").with_effect(wave).with_scale(2),
+        ], Handle::default());
+        let layout = layout_text(&text, &font, None);
+        let offset = |index: usize, time: f32| effect_offset(&text, &layout.glyphs[index], time);
+
+        assert_eq!(offset(0, 0.25), IVec2::ZERO);
+        assert_eq!(offset(1, 0.1), IVec2::ZERO);
+        assert_eq!(offset(1, 0.35), IVec2::new(0, -4));
+        assert_eq!(offset(5, 0.75), IVec2::new(0, -4));
+    }
+
+    #[test]
+    fn waves_stay_on_the_canvas() {
+        let font = test_font();
+        let wave = TextEffect::Wave { amplitude: 2., speed: 1. };
+        let text = PxText::from_sections(vec![
+            PxTextSection::new("ab").with_effect(wave).with_scale(2),
+        ], Handle::default());
+        let layout = layout_text(&text, &font, None);
+        let opaque = |time: f32| {
+            let output = draw_text(&text, &font, &layout, time, usize::MAX);
+            assert_eq!(output.dimensions(), (layout.size + 8).into());
+            output.pixels().filter(|px| px[3] != 0).count()
+        };
+
+        assert_eq!(effect_margin(&text), 4);
+        assert_eq!(opaque(0.35), opaque(0.));
+        assert_eq!(opaque(0.85), opaque(0.));
+    }
 }
@@ -0,0 +1,244 @@
+use bevy::{prelude::*, render::render_resource::Extent3d, ui::{AvailableSpace, ContentSize, Measure, NodeMeasure}, window::PrimaryWindow};
+
+use crate::{layout::{fit_text, FontLoad, TextLayout}, pxfont::PxFont, pxtext::{PxText, PxTextNode, WrapMode, WritingMode}, render_text::{effect_margin, font_load, text_fonts, text_image, uses_font, FontEvents, LaidOutText, TextLayouts}};
+
+/// Sizes text nodes in physical pixels, wrapping to the width the UI layout offers
+struct PxTextMeasure {
+    text: PxText,
+    /// The text's fonts that didn't fail to load, with their metrics once they're loaded
+    fonts: Vec<(AssetId<PxFont>, Option<PxFont>)>,
+    scale: u32,
+}
+
+impl Measure for PxTextMeasure {
+    fn measure(
+        &self,
+        width: Option<f32>,
+        _height: Option<f32>,
+        available_width: AvailableSpace,
+        _available_height: AvailableSpace,
+        _style: &taffy::Style,
+    ) -> Vec2 {
+        let max_width = width.or(match available_width {
+            AvailableSpace::Definite(width) => Some(width),
+            AvailableSpace::MinContent => self.min_content_width()
+                .map(|width| (width * self.scale) as f32),
+            AvailableSpace::MaxContent => None,
+        });
+
+        // Leave room around the text for its effects to move it
+        let margin = 2 * effect_margin(&self.text);
+        let mut text = self.text.clone();
+        text.bounding_box = match text.writing_mode {
+            WritingMode::HorizontalTb => max_width
+                .map(|width| UVec2::new(((width / self.scale as f32) as u32).saturating_sub(margin), u32::MAX)),
+            _ => None,
+        };
+
+        fit_text(&text, None, |handle| self.font(handle))
+            .map(|fitted| fitted.layout.content + margin)
+            .unwrap_or_default()
+            .as_vec2() * self.scale as f32
+    }
+}
+
+impl PxTextMeasure {
+    /// Picks among the fonts the same way the text is rendered
+    fn font(&self, handle: &Handle<PxFont>) -> FontLoad<'_> {
+        match self.fonts.iter().find(|(id, _)| *id == handle.id()) {
+            Some((_, Some(font))) => FontLoad::Loaded(font),
+            Some((_, None)) => FontLoad::Loading,
+            None => FontLoad::Failed,
+        }
+    }
+
+    /// Finds how narrow the text can wrap without breaking inside a word,
+    /// or `None` if it doesn't wrap
+    fn min_content_width(&self) -> Option<u32> {
+        if self.text.writing_mode != WritingMode::HorizontalTb || self.text.wrap_mode == WrapMode::NoWrap {
+            return None;
+        }
+        let text = PxText { bounding_box: None, ..self.text.clone() };
+        let fitted = fit_text(&text, None, |handle| self.font(handle))?;
+        Some(widest_unbreakable(&fitted.layout, self.text.wrap_mode == WrapMode::WrapChar))
+    }
+}
+
+/// Finds the width of the widest run of glyphs a line can't break inside, like the longest word.
+/// With `break_anywhere`, that's the widest glyph.
+fn widest_unbreakable(layout: &TextLayout, break_anywhere: bool) -> u32 {
+    let mut widest = 0;
+    let mut run: Option<(usize, u32)> = None;
+
+    for glyph in &layout.glyphs {
+        if glyph.c.is_whitespace() {
+            run = None;
+            continue;
+        }
+        let start = match run {
+            Some((line, start)) if line == glyph.line && !glyph.can_break && !break_anywhere => start,
+            _ => glyph.pos.x,
+        };
+        run = Some((glyph.line, start));
+        widest = widest.max(glyph.pos.x + glyph.size.x - start);
+    }
+
+    widest
+}
+
+/// Finds how many physical pixels each pixel of text takes up, rounded down to a whole number
+fn pixel_scale(windows: &Query<&Window, With<PrimaryWindow>>, ui_scale: &UiScale) -> (f32, u32) {
+    let combined_scale_factor = windows
+        .get_single()
+        .map(|window| window.resolution.scale_factor())
+        .unwrap_or(1.)
+        * ui_scale.0;
+
+    (combined_scale_factor, (combined_scale_factor.floor() as u32).max(1))
+}
+
+pub(crate) fn measure_text_node_system(
+    fonts: Res<Assets<PxFont>>,
+    asset_server: Res<AssetServer>,
+    mut font_events: FontEvents,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    ui_scale: Res<UiScale>,
+    mut previous_scale: Local<u32>,
+    mut q_text: Query<(Ref<PxText>, &mut ContentSize), With<PxTextNode>>,
+) {
+    let loaded = font_events.read();
+    let (_, scale) = pixel_scale(&windows, &ui_scale);
+
+    for (text, mut content_size) in &mut q_text {
+        if !text.is_changed() && scale == *previous_scale
+            && !loaded.iter().any(|id| uses_font(&text, *id)) {
+            continue;
+        }
+        let text_fonts: Vec<_> = text_fonts(&text)
+            .filter_map(|handle| match font_load(&fonts, &asset_server, handle) {
+                FontLoad::Loaded(font) => Some((handle.id(), Some(font.metrics()))),
+                FontLoad::Loading => Some((handle.id(), None)),
+                FontLoad::Failed => None,
+            })
+            .collect();
+        // Wait for a font to load, which measures the text again
+        if text_fonts.iter().all(|(_, font)| font.is_none()) {
+            continue;
+        }
+
+        content_size.set(NodeMeasure::Custom(Box::new(PxTextMeasure {
+            text: text.clone(),
+            fonts: text_fonts,
+            scale,
+        })));
+    }
+
+    *previous_scale = scale;
+}
+
+pub(crate) fn render_text_node_system(
+    mut layouts: TextLayouts,
+    mut images: ResMut<Assets<Image>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    ui_scale: Res<UiScale>,
+    mut previous_scale: Local<u32>,
+    mut q_text: Query<(LaidOutText, Ref<Node>, &mut UiImage), With<PxTextNode>>,
+    mut commands: Commands,
+) {
+    let loaded = layouts.changed_fonts();
+    let (combined_scale_factor, scale) = pixel_scale(&windows, &ui_scale);
+
+    for (text, node, mut ui_image) in &mut q_text {
+        let relayout = text.changed(&loaded) || node.is_changed() || scale != *previous_scale;
+        let bounded = match relayout {
+            true => {
+                // Fill the node, which the UI layout sized to fit the text and the room its effects need
+                let margin = 2 * effect_margin(&text.text);
+                let bounds = (node.size() * combined_scale_factor / scale as f32).as_uvec2();
+                if bounds.cmple(UVec2::splat(margin)).any() {
+                    continue;
+                }
+                Some(PxText { bounding_box: Some(bounds - margin), ..PxText::clone(&text.text) })
+            }
+            // Animated text is drawn again from its last layout
+            false => None,
+        };
+        let Some(draw) = layouts.layout(&mut commands, &text, bounded.as_ref().unwrap_or(&text.text), relayout, None) else {
+            continue;
+        };
+        let output = draw.draw();
+
+        match images.get_mut(&ui_image.texture).filter(|_| ui_image.texture.is_strong()) {
+            Some(image) => {
+                let size = Extent3d {
+                    width: output.width(),
+                    height: output.height(),
+                    depth_or_array_layers: 1,
+                };
+                if image.texture_descriptor.size != size {
+                    image.resize(size);
+                }
+                image.data = output.into_vec();
+            }
+            None => {
+                ui_image.texture = images.add(text_image(UVec2::new(output.width(), output.height()), output.into_vec()));
+            }
+        }
+
+        draw.store(&mut commands, text.entity);
+    }
+
+    *previous_scale = scale;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pxtext::FitMode, test_support::test_font};
+
+    #[test]
+    fn min_content_is_the_longest_word() {
+        let measure = PxTextMeasure {
+            text: PxText::from_section("ab cdef g", Handle::default()),
+            fonts: vec![(AssetId::default(), Some(test_font()))],
+            scale: 2,
+        };
+        let size = measure.measure(
+            None,
+            None,
+            AvailableSpace::MinContent,
+            AvailableSpace::MaxContent,
+            &taffy::Style::default(),
+        );
+
+        // "cdef" is 15 pixels wide, on the second of three lines
+        assert_eq!(size, Vec2::new(30., 28.));
+    }
+
+    #[test]
+    fn measures_with_the_font_that_fits() {
+        let mut large = test_font();
+        for glyph in large.char_map.values_mut() {
+            glyph.src_rect.max.x += 4;
+        }
+        let (primary, fallback) = (Handle::default(), Handle::weak_from_u128(1));
+        let mut text = PxText::from_section("abc", primary.clone())
+            .with_fit(FitMode::Fonts(vec![fallback.clone()]));
+        text.wrap_mode = WrapMode::Truncate;
+        let measure = |primary_font: Option<Option<&PxFont>>| {
+            let fonts = primary_font
+                .map(|font| (primary.id(), font.map(PxFont::metrics)))
+                .into_iter()
+                .chain([(fallback.id(), Some(test_font()))])
+                .collect();
+            let measure = PxTextMeasure { text: text.clone(), fonts, scale: 1 };
+            measure.measure(None, None, AvailableSpace::Definite(12.), AvailableSpace::MaxContent, &taffy::Style::default())
+        };
+
+        // "abc" only fits in 12 pixels in the fallback font, which is only
+        // picked once the primary font loaded or failed
+        assert_eq!(measure(Some(Some(&large))), Vec2::new(11., 4.));
+        assert_eq!(measure(Some(None)), Vec2::ZERO);
+        assert_eq!(measure(None), Vec2::new(11., 4.));
+    }
+}
@@ -0,0 +1,49 @@
+use bevy::prelude::*;
+
+use crate::pxtext::{PxTextLayoutInfo, PxTextSnap};
+
+/// Moves text with `PxTextSnap` so that its pixels line up with the grid they make on screen.
+/// Only the rendered position changes, so the `Transform` can keep moving smoothly.
+#[allow(clippy::type_complexity)]
+pub(crate) fn snap_text_system(
+    q_camera: Query<(&Camera, &OrthographicProjection, &GlobalTransform)>,
+    mut q_text: Query<(&mut GlobalTransform, &mut PxTextSnap, &PxTextLayoutInfo), Without<Camera>>,
+) {
+    let Some((camera, projection, camera_transform)) = q_camera
+        .iter()
+        .filter(|(camera, ..)| camera.is_active)
+        .max_by_key(|(camera, ..)| camera.order)
+    else {
+        return;
+    };
+    let Some(viewport) = camera.physical_viewport_size() else {
+        return;
+    };
+    // World units covered by one physical pixel
+    let unit = projection.area.size() / viewport.as_vec2();
+    let origin = camera_transform.translation().truncate();
+
+    for (mut global_transform, mut snap, info) in &mut q_text {
+        let mut transform = global_transform.compute_transform();
+        // Transforms that weren't propagated again still hold last frame's snapped position
+        if let Some((snapped, offset)) = snap.last {
+            if snapped == transform.translation {
+                transform.translation -= offset;
+            }
+        }
+        let scale = transform.scale.truncate();
+
+        // Text is centered, so images with odd sizes start halfway through a pixel
+        let size = info.size.as_vec2() * scale;
+        let corner = transform.translation.truncate() - origin + Vec2::new(-size.x, size.y) / 2.;
+
+        // Each pixel of text covers a whole number of screen pixels
+        let step = unit * (scale / unit).round().max(Vec2::ONE);
+        let snapped = (corner / step).round() * step;
+
+        let offset = (snapped - corner).extend(0.);
+        transform.translation += offset;
+        snap.last = Some((transform.translation, offset));
+        *global_transform = transform.into();
+    }
+}
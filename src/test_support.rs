@@ -0,0 +1,36 @@
+use ahash::AHashMap;
+use bevy::prelude::*;
+use image::RgbaImage;
+
+use crate::pxfont::{PxFont, PxGlyph};
+
+/// A font where most glyphs are 3 pixels wide, spaces are 2 and a period
+/// and an opening bracket are 1, with 1 pixel of spacing and lines 4 pixels tall. Glyphs advance
+/// 5 pixels in vertical text.
+pub(crate) fn test_font() -> PxFont {
+    let mut char_map = AHashMap::new();
+    let mut x = 0;
+    for c in " -.abcdefghijklmnopqrstuvwxyz\u{A0}日本語。אבג()".chars() {
+        let width = match c {
+            ' ' | '\u{A0}' => 2,
+            '.' | '(' => 1,
+            _ => 3,
+        };
+        char_map.insert(c, PxGlyph {
+            src_rect: URect::new(x, 0, x + width - 1, 3),
+        });
+        x += width;
+    }
+
+    PxFont {
+        name: "Test".into(),
+        source: RgbaImage::new(x, 4),
+        atlas: Handle::default(),
+        char_map,
+        ascender: 3,
+        descender: 1,
+        spacing: 1,
+        vertical_advance: 5,
+        space_width: None,
+    }
+}
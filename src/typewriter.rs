@@ -0,0 +1,67 @@
+use bevy::prelude::*;
+
+use crate::pxtext::{PxText, PxTextLayoutInfo, PxTextPages, PxTypewriter, PxTypewriterChar, PxTypewriterFinished};
+
+/// Reveals the characters of each `PxTypewriter` whose wait is over.
+/// With `PxTextPages`, only the characters of the page shown are revealed, starting over on each page.
+/// The typewriter is only marked as changed when more of its text shows, which redraws it.
+#[allow(clippy::type_complexity)]
+pub(crate) fn typewriter_system(
+    time: Res<Time>,
+    mut q_text: Query<(
+        Entity,
+        Ref<PxText>,
+        &mut PxTypewriter,
+        Option<Ref<PxTextPages>>,
+        Option<&PxTextLayoutInfo>,
+    )>,
+    mut char_evw: EventWriter<PxTypewriterChar>,
+    mut finished_evw: EventWriter<PxTypewriterFinished>,
+) {
+    for (entity, text, mut typewriter, pages, info) in &mut q_text {
+        let turned = pages.as_ref().is_some_and(|pages| pages.is_changed() && !pages.is_added());
+        if (text.is_changed() && !text.is_added()) || turned {
+            typewriter.restart();
+        }
+        if typewriter.finished {
+            continue;
+        }
+
+        let chars: Vec<char> = text.sections
+            .iter()
+            .flat_map(|section| section.value.chars())
+            .collect();
+        // Wait for the page to be laid out to know which characters are on it
+        let range = match (pages, info) {
+            (None, _) => 0..chars.len(),
+            (Some(pages), Some(info)) if !text.is_changed() && info.page as usize == pages.page => {
+                info.page_chars.start..info.page_chars.end.min(chars.len())
+            }
+            _ => continue,
+        };
+        let writer = typewriter.bypass_change_detection();
+        let start = writer.revealed;
+        writer.revealed = writer.revealed.max(range.start);
+
+        if writer.skipped {
+            writer.revealed = range.end;
+        } else {
+            writer.wait -= time.delta_seconds();
+            // Slow frames can reveal several characters at once
+            while writer.revealed < range.end && writer.wait <= 0. {
+                let c = chars[writer.revealed];
+                char_evw.send(PxTypewriterChar { entity, index: writer.revealed, char: c });
+                writer.revealed += 1;
+                writer.wait += writer.delay(c);
+            }
+        }
+
+        if writer.revealed >= range.end {
+            writer.finished = true;
+            finished_evw.send(PxTypewriterFinished { entity });
+        }
+        if writer.revealed != start {
+            typewriter.set_changed();
+        }
+    }
+}